@@ -1,30 +1,109 @@
-use crate::token::Token;
+use std::fmt;
 
-#[derive(Debug)]
+use crate::{parser::Precedence, span::Span, token::Token};
+
+#[derive(Debug, Clone)]
 pub enum Statement {
     Let {
         token: Token,
         name: Identifier,
-        // value: Node,
+        value: Expression,
     },
     Return {
         token: Token,
-        // value: Node,
+        value: Expression,
+    },
+    Expression {
+        token: Token,
+        value: Expression,
     },
 }
 
-#[derive(Debug, Default)]
-pub struct Identifier {
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Identifier(Identifier),
+    Integer {
+        token: Token,
+        value: i64,
+    },
+    Float {
+        token: Token,
+        value: f64,
+    },
+    Boolean {
+        token: Token,
+        value: bool,
+    },
+    String {
+        token: Token,
+        value: String,
+    },
+    Prefix {
+        token: Token,
+        operator: String,
+        right: Box<Expression>,
+    },
+    Infix {
+        token: Token,
+        left: Box<Expression>,
+        operator: String,
+        right: Box<Expression>,
+    },
+    Grouped {
+        token: Token,
+        expression: Box<Expression>,
+        span: Span,
+    },
+    If {
+        token: Token,
+        condition: Box<Expression>,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    },
+    Function {
+        token: Token,
+        parameters: Vec<Identifier>,
+        body: BlockStatement,
+    },
+    Call {
+        token: Token,
+        function: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    Array {
+        token: Token,
+        elements: Vec<Expression>,
+        span: Span,
+    },
+    Index {
+        token: Token,
+        left: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+    Hash {
+        token: Token,
+        pairs: Vec<(Expression, Expression)>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BlockStatement {
+    #[allow(dead_code)]
     token: Token,
-    value: String,
+    statements: Vec<Statement>,
+    span: Span,
 }
 
-#[derive(Debug, Default)]
-pub struct Node {
+#[derive(Debug, Default, Clone)]
+pub struct Identifier {
     token: Token,
+    value: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Program {
     statements: Vec<Statement>,
 }
@@ -45,6 +124,65 @@ impl Program {
     }
 }
 
+impl Statement {
+    pub fn get_span(&self) -> Span {
+        match self {
+            Statement::Let { token, value, .. } | Statement::Return { token, value } => {
+                token.get_span().merge(value.get_span())
+            }
+            Statement::Expression { value, .. } => value.get_span(),
+        }
+    }
+}
+
+impl Expression {
+    pub fn get_span(&self) -> Span {
+        match self {
+            Expression::Identifier(identifier) => identifier.get_span(),
+            Expression::Integer { token, .. }
+            | Expression::Float { token, .. }
+            | Expression::Boolean { token, .. }
+            | Expression::String { token, .. } => token.get_span(),
+            Expression::Prefix { token, right, .. } => token.get_span().merge(right.get_span()),
+            Expression::Infix { left, right, .. } => left.get_span().merge(right.get_span()),
+            Expression::If {
+                token,
+                consequence,
+                alternative,
+                ..
+            } => {
+                let last_block = alternative.as_ref().unwrap_or(consequence);
+                token.get_span().merge(last_block.get_span())
+            }
+            Expression::Function { token, body, .. } => token.get_span().merge(body.get_span()),
+            Expression::Grouped { span, .. }
+            | Expression::Call { span, .. }
+            | Expression::Array { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::Hash { span, .. } => *span,
+        }
+    }
+}
+
+impl BlockStatement {
+    pub fn new(token: Token, statements: Vec<Statement>, span: Span) -> BlockStatement {
+        BlockStatement {
+            token,
+            statements,
+            span,
+        }
+    }
+
+    pub fn get_statements(&self) -> &Vec<Statement> {
+        &self.statements
+    }
+
+    /// Span from the opening to the closing brace.
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+}
+
 impl Identifier {
     pub fn new(token: Token, value: String) -> Identifier {
         Identifier { token, value }
@@ -57,4 +195,543 @@ impl Identifier {
     pub fn get_value(&self) -> &String {
         &self.value
     }
+
+    pub fn get_span(&self) -> Span {
+        self.token.get_span()
+    }
+}
+
+/// Write a child node, keeping the fully parenthesized form of `{:#}`.
+fn write_node<T: fmt::Display>(f: &mut fmt::Formatter, node: &T) -> fmt::Result {
+    if f.alternate() {
+        write!(f, "{:#}", node)
+    } else {
+        write!(f, "{}", node)
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, nodes: &[T]) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_node(f, node)?;
+    }
+
+    Ok(())
+}
+
+/// Write an operand of an operator, in parentheses if it binds weaker than the operator.
+fn write_operand(f: &mut fmt::Formatter, operand: &Expression, parenthesize: bool) -> fmt::Result {
+    if parenthesize && !f.alternate() {
+        write!(f, "({})", operand)
+    } else {
+        write_node(f, operand)
+    }
+}
+
+impl Expression {
+    /// How strong the expression binds when it is an operand, literals and everything else
+    /// that can not be split up bind the strongest.
+    fn get_precedence(&self) -> Precedence {
+        match self {
+            Expression::Infix { token, .. } => Precedence::from_token_type(&token.get_type()),
+            Expression::Prefix { .. } => Precedence::Prefix,
+            Expression::Grouped { expression, .. } => expression.get_precedence(),
+            _ => Precedence::Index,
+        }
+    }
+}
+
+/// Prints one statement per line, which parses back into the same program.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write_node(f, statement)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Let { name, value, .. } => {
+                write!(f, "let {} = ", name)?;
+                write_node(f, value)?;
+            }
+            Statement::Return { value, .. } => {
+                write!(f, "return ")?;
+                write_node(f, value)?;
+            }
+            Statement::Expression { value, .. } => write_node(f, value)?,
+        }
+
+        write!(f, ";")
+    }
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{}}");
+        }
+
+        write!(f, "{{")?;
+        for statement in &self.statements {
+            write!(f, " ")?;
+            write_node(f, statement)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+/// Prints source code with as few parentheses as possible, parentheses of the original source
+/// are only kept where they are needed. The alternate form `{:#}` puts every prefix and infix
+/// expression in parentheses instead, which shows how the expression is grouped.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Identifier(identifier) => write!(f, "{}", identifier),
+            Expression::Integer { value, .. } => write!(f, "{}", value),
+            // a literal that is too large for a float is infinity, print one that is as well
+            Expression::Float { value, .. } if value.is_infinite() => write!(f, "1e999"),
+            Expression::Float { value, .. } => write!(f, "{:?}", value),
+            Expression::Boolean { value, .. } => write!(f, "{}", value),
+            Expression::String { value, .. } => {
+                write!(f, "\"")?;
+                for ch in value.chars() {
+                    match ch {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        ch if ch.is_control() => write!(f, "\\u{{{:x}}}", ch as u32)?,
+                        ch => write!(f, "{}", ch)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expression::Prefix {
+                operator, right, ..
+            } => {
+                let parenthesize = right.get_precedence() < Precedence::Prefix;
+                if f.alternate() {
+                    write!(f, "({}{:#})", operator, right)
+                } else {
+                    write!(f, "{}", operator)?;
+                    write_operand(f, right, parenthesize)
+                }
+            }
+            Expression::Infix {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                // operators are left associative, so only the right side needs parentheses on
+                // the same level
+                let precedence = self.get_precedence();
+                if f.alternate() {
+                    write!(f, "({:#} {} {:#})", left, operator, right)
+                } else {
+                    write_operand(f, left, left.get_precedence() < precedence)?;
+                    write!(f, " {} ", operator)?;
+                    write_operand(f, right, right.get_precedence() <= precedence)
+                }
+            }
+            Expression::Grouped { expression, .. } => write_node(f, expression.as_ref()),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                write!(f, "if (")?;
+                write_node(f, condition.as_ref())?;
+                write!(f, ") ")?;
+                write_node(f, consequence)?;
+
+                if let Some(alternative) = alternative {
+                    write!(f, " else ")?;
+                    write_node(f, alternative)?;
+                }
+
+                Ok(())
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                write!(f, "fn(")?;
+                write_list(f, parameters)?;
+                write!(f, ") ")?;
+                write_node(f, body)
+            }
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                write_operand(f, function, function.get_precedence() < Precedence::Call)?;
+                write!(f, "(")?;
+                write_list(f, arguments)?;
+                write!(f, ")")
+            }
+            Expression::Array { elements, .. } => {
+                write!(f, "[")?;
+                write_list(f, elements)?;
+                write!(f, "]")
+            }
+            Expression::Index { left, index, .. } => {
+                write_operand(f, left, left.get_precedence() < Precedence::Call)?;
+                write!(f, "[")?;
+                write_node(f, index.as_ref())?;
+                write!(f, "]")
+            }
+            Expression::Hash { pairs, .. } => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_node(f, key)?;
+                    write!(f, ": ")?;
+                    write_node(f, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockStatement, Expression, Program, Statement};
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn parse(input: &str) -> Program {
+        Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .unwrap_or_else(|errors| panic!("could not parse {:?}:\n{}", input, errors))
+    }
+
+    #[test]
+    fn test_display_minimal_parentheses() {
+        let tests = [
+            ("1 + 2 * 3", "1 + 2 * 3;"),
+            ("(1 + 2) * 3", "(1 + 2) * 3;"),
+            ("((a)) - (b - c) - d", "a - (b - c) - d;"),
+            ("(a - b) - c", "a - b - c;"),
+            ("-(a + b) + -a", "-(a + b) + -a;"),
+            ("!(-a)", "!-a;"),
+            ("(-f)(1)[0] + f(1)[2]", "(-f)(1)[0] + f(1)[2];"),
+            ("(a || b) && c || d", "(a || b) && c || d;"),
+            ("a <= (b == c)", "a <= (b == c);"),
+            ("let s = \"a\\\"\\n\\u{7}\";", "let s = \"a\\\"\\n\\u{7}\";"),
+            ("let x = 1.5e3; return 0x10;", "let x = 1500.0;\nreturn 16;"),
+            (
+                "let f = fn(a, b) { if (a) { return b } else { a }; }",
+                "let f = fn(a, b) { if (a) { return b; } else { a; }; };",
+            ),
+            (
+                "fn() {}(); [1, {true: 2}][0]",
+                "fn() {}();\n[1, {true: 2}][0];",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_display_parenthesized() {
+        let tests = [
+            ("1 + 2 * 3", "(1 + (2 * 3));"),
+            ("-a * b", "((-a) * b);"),
+            ("a - (b - c) - d", "((a - (b - c)) - d);"),
+            ("!-f(1)[0]", "(!(-f(1)[0]));"),
+            ("let x = fn(a) { a + 1 };", "let x = fn(a) { (a + 1); };"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(format!("{:#}", parse(input)), expected, "input: {}", input);
+        }
+    }
+
+    /// Structure of a syntax tree without tokens and spans. Parentheses are not part of it, they
+    /// only show up in the nesting of the nodes.
+    #[derive(Debug, PartialEq)]
+    struct Node {
+        kind: &'static str,
+        value: String,
+        children: Vec<Node>,
+    }
+
+    impl Node {
+        fn new(kind: &'static str, value: impl ToString, children: Vec<Node>) -> Node {
+            Node {
+                kind,
+                value: value.to_string(),
+                children,
+            }
+        }
+
+        fn program(program: &Program) -> Node {
+            Node::new("program", "", Node::statements(&program.statements))
+        }
+
+        fn statements(statements: &[Statement]) -> Vec<Node> {
+            statements.iter().map(Node::statement).collect()
+        }
+
+        fn block(block: &BlockStatement) -> Node {
+            Node::new("block", "", Node::statements(&block.statements))
+        }
+
+        fn statement(statement: &Statement) -> Node {
+            match statement {
+                Statement::Let { name, value, .. } => {
+                    Node::new("let", &name.value, vec![Node::expression(value)])
+                }
+                Statement::Return { value, .. } => {
+                    Node::new("return", "", vec![Node::expression(value)])
+                }
+                Statement::Expression { value, .. } => {
+                    Node::new("expression", "", vec![Node::expression(value)])
+                }
+            }
+        }
+
+        fn expression(expression: &Expression) -> Node {
+            let list = |expressions: &[Expression]| -> Vec<Node> {
+                expressions.iter().map(Node::expression).collect()
+            };
+
+            match expression {
+                Expression::Identifier(identifier) => {
+                    Node::new("identifier", &identifier.value, vec![])
+                }
+                Expression::Integer { value, .. } => Node::new("integer", value, vec![]),
+                // the bits tell apart values that print the same, e.g. `0.0` and `-0.0`
+                Expression::Float { value, .. } => Node::new("float", value.to_bits(), vec![]),
+                Expression::Boolean { value, .. } => Node::new("boolean", value, vec![]),
+                Expression::String { value, .. } => Node::new("string", value, vec![]),
+                Expression::Prefix {
+                    operator, right, ..
+                } => Node::new("prefix", operator, vec![Node::expression(right)]),
+                Expression::Infix {
+                    left,
+                    operator,
+                    right,
+                    ..
+                } => Node::new(
+                    "infix",
+                    operator,
+                    vec![Node::expression(left), Node::expression(right)],
+                ),
+                Expression::Grouped { expression, .. } => Node::expression(expression),
+                Expression::If {
+                    condition,
+                    consequence,
+                    alternative,
+                    ..
+                } => {
+                    let mut children = vec![Node::expression(condition), Node::block(consequence)];
+                    children.extend(alternative.as_ref().map(Node::block));
+                    Node::new("if", "", children)
+                }
+                Expression::Function {
+                    parameters, body, ..
+                } => {
+                    let parameters: Vec<&str> =
+                        parameters.iter().map(|p| p.value.as_str()).collect();
+                    Node::new("function", parameters.join(", "), vec![Node::block(body)])
+                }
+                Expression::Call {
+                    function,
+                    arguments,
+                    ..
+                } => {
+                    let mut children = vec![Node::expression(function)];
+                    children.extend(list(arguments));
+                    Node::new("call", "", children)
+                }
+                Expression::Array { elements, .. } => Node::new("array", "", list(elements)),
+                Expression::Index { left, index, .. } => Node::new(
+                    "index",
+                    "",
+                    vec![Node::expression(left), Node::expression(index)],
+                ),
+                Expression::Hash { pairs, .. } => {
+                    let pairs = pairs
+                        .iter()
+                        .map(|(key, value)| {
+                            Node::new(
+                                "pair",
+                                "",
+                                vec![Node::expression(key), Node::expression(value)],
+                            )
+                        })
+                        .collect();
+                    Node::new("hash", "", pairs)
+                }
+            }
+        }
+    }
+
+    /// Pseudo random source code from a fixed seed, valid but not necessarily meaningful.
+    struct Generator {
+        state: u64,
+    }
+
+    impl Generator {
+        fn next(&mut self, bound: usize) -> usize {
+            // xorshift
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            (self.state % bound as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+            choices[self.next(choices.len())]
+        }
+
+        fn list(&mut self, depth: usize, max: usize) -> String {
+            let count = self.next(max + 1);
+            (0..count)
+                .map(|_| self.expression(depth))
+                .collect::<Vec<String>>()
+                .join(", ")
+        }
+
+        fn block(&mut self, depth: usize) -> String {
+            let count = self.next(3);
+            let statements: Vec<String> = (0..count).map(|_| self.statement(depth)).collect();
+            format!("{{ {} }}", statements.join(" "))
+        }
+
+        fn statement(&mut self, depth: usize) -> String {
+            match self.next(4) {
+                0 => format!(
+                    "let {} = {};",
+                    self.pick(&["a", "b", "f"]),
+                    self.expression(depth)
+                ),
+                1 => format!("return {};", self.expression(depth)),
+                _ => format!("{};", self.expression(depth)),
+            }
+        }
+
+        fn expression(&mut self, depth: usize) -> String {
+            let choice = if depth == 0 {
+                self.next(5)
+            } else {
+                self.next(16)
+            };
+            let depth = depth.saturating_sub(1);
+
+            match choice {
+                0 => self.pick(&["a", "b", "f", "len"]).into(),
+                1 => self
+                    .pick(&["0", "7", "1_000", "0xff", "9223372036854775807"])
+                    .into(),
+                2 => self.pick(&["1.5", "0.25", "2e3", "1e-7", "1e999"]).into(),
+                3 => self.pick(&["true", "false"]).into(),
+                4 => self
+                    .pick(&["\"\"", "\"a b\"", "\"q\\\"\\\\\"", "\"\\t\\u{1F600}\\n\""])
+                    .into(),
+                5..=7 => {
+                    let operator = self.pick(&[
+                        "+", "-", "*", "/", "%", "<", ">", "<=", ">=", "==", "!=", "&&", "||",
+                    ]);
+                    format!(
+                        "{} {} {}",
+                        self.expression(depth),
+                        operator,
+                        self.expression(depth)
+                    )
+                }
+                8 => format!("{}{}", self.pick(&["-", "!"]), self.expression(depth)),
+                9 => format!("({})", self.expression(depth)),
+                10 => format!("{}({})", self.expression(depth), self.list(depth, 2)),
+                11 => format!("{}[{}]", self.expression(depth), self.expression(depth)),
+                12 => format!("[{}]", self.list(depth, 3)),
+                13 => {
+                    let pairs: Vec<String> = (0..self.next(3))
+                        .map(|_| format!("{}: {}", self.expression(depth), self.expression(depth)))
+                        .collect();
+                    format!("{{{}}}", pairs.join(", "))
+                }
+                14 => {
+                    let parameters = ["", "x", "x, y"][self.next(3)];
+                    format!("fn({}) {}", parameters, self.block(depth))
+                }
+                _ => {
+                    let condition = self.expression(depth);
+                    let consequence = self.block(depth);
+                    match self.next(2) {
+                        0 => format!("if ({}) {}", condition, consequence),
+                        _ => format!(
+                            "if ({}) {} else {}",
+                            condition,
+                            consequence,
+                            self.block(depth)
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let shape = |input| Node::program(&parse(input));
+        assert_eq!(shape("((a)) + 1.0;"), shape("a + 1.0"));
+        assert_ne!(shape("(1 + 2) * 3"), shape("1 + 2 * 3"));
+        assert_ne!(shape("-0.0"), shape("0.0"));
+
+        let mut generator = Generator {
+            state: 0x2545_f491_4f6c_dd1d,
+        };
+
+        for _ in 0..500 {
+            let count = 1 + generator.next(3);
+            let source: Vec<String> = (0..count).map(|_| generator.statement(4)).collect();
+            let source = source.join("\n");
+
+            // spans and tokens differ between the programs, only their structure is compared
+            let program = parse(&source);
+            let printed = program.to_string();
+            let reparsed = parse(&printed);
+            assert_eq!(
+                Node::program(&reparsed),
+                Node::program(&program),
+                "source: {}\nprinted: {}",
+                source,
+                printed
+            );
+            assert_eq!(reparsed.to_string(), printed, "source: {}", source);
+
+            let parenthesized = format!("{:#}", program);
+            assert_eq!(
+                Node::program(&parse(&parenthesized)),
+                Node::program(&program),
+                "source: {}\nparenthesized: {}",
+                source,
+                parenthesized
+            );
+        }
+    }
 }
@@ -0,0 +1,903 @@
+mod code;
+mod disassembler;
+mod symbol_table;
+mod tbc;
+
+pub use code::{format_instructions, make, read_operands, Opcode};
+pub use disassembler::disassemble;
+pub use tbc::{DecodeError, MAGIC, VERSION};
+
+use std::{fmt, rc::Rc};
+
+use crate::{
+    ast::{BlockStatement, Expression, Identifier, Program, Statement},
+    diagnostics::Diagnostic,
+    span::{SourceMap, Span},
+};
+use symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+/// Instructions of a function (or the main program) with the span of the source each
+/// instruction was compiled from, sorted by offset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledFunction {
+    instructions: Vec<u8>,
+    spans: Vec<(usize, Span)>,
+    num_locals: usize,
+    parameters: Vec<String>,
+    /// Variables of the enclosing function that are captured when the closure is created.
+    free: Vec<Origin>,
+    /// What each local after the parameters reads until it is bound, like a name in the
+    /// evaluator is looked up in the enclosing scopes until it is set in the current one.
+    fallbacks: Vec<Origin>,
+}
+
+/// Variable that another one refers to, relative to the function that creates the closure for
+/// free variables and relative to the function itself for fallbacks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    Local(usize),
+    Free(usize),
+    Global(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Function(Rc<CompiledFunction>),
+}
+
+/// Output of the compiler, which is run by the vm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    main: Rc<CompiledFunction>,
+    constants: Vec<Constant>,
+    global_names: Vec<String>,
+    /// Offsets where the lines of the source start, to find the line of a span without the
+    /// source.
+    line_starts: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    message: String,
+    span: Span,
+}
+
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Vec<u8>,
+    spans: Vec<(usize, Span)>,
+    last_instruction: Option<(Opcode, usize)>,
+}
+
+pub struct Compiler {
+    constants: Vec<Constant>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+/// Compile a program, `source` is the code it was parsed from and is used for the line table.
+pub fn compile(program: &Program, source: &str) -> Result<Bytecode, CompileError> {
+    let mut compiler = Compiler::new();
+    compiler.compile_program(program)?;
+
+    let mut bytecode = compiler.into_bytecode();
+    bytecode.line_starts = SourceMap::new(source).get_line_starts().clone();
+    Ok(bytecode)
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            constants: Vec::new(),
+            symbol_table: SymbolTable::new(),
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn compile_program(&mut self, program: &Program) -> Result<(), CompileError> {
+        for statement in program.get_statements() {
+            self.compile_statement(statement)?;
+        }
+
+        // the result of a program is the value of its last statement, which is null for `let`
+        match program.get_statements().last() {
+            Some(Statement::Expression { .. }) => {}
+            last => {
+                let span = last.map_or(Span::default(), |s| s.get_span());
+                self.emit(Opcode::Null, &[], span)?;
+                self.emit(Opcode::Pop, &[], span)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn into_bytecode(mut self) -> Bytecode {
+        let scope = self.scopes.pop().expect("the main scope is never left");
+
+        Bytecode {
+            main: Rc::new(CompiledFunction {
+                instructions: scope.instructions,
+                spans: scope.spans,
+                ..Default::default()
+            }),
+            global_names: self.symbol_table.get_global_names().clone(),
+            constants: self.constants,
+            line_starts: vec![0],
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        let span = statement.get_span();
+
+        match statement {
+            Statement::Let { name, value, .. } => {
+                self.compile_expression(value)?;
+
+                // defined after the value, so that the value can still refer to a previous binding
+                let symbol = self.symbol_table.define(name.get_value());
+                match symbol.get_scope() {
+                    SymbolScope::Global => {
+                        self.emit(Opcode::SetGlobal, &[symbol.get_index()], span)?
+                    }
+                    _ => self.emit(Opcode::SetLocal, &[symbol.get_index()], span)?,
+                };
+            }
+            Statement::Return { value, .. } => {
+                self.compile_expression(value)?;
+                self.emit(Opcode::ReturnValue, &[], span)?;
+            }
+            Statement::Expression { value, .. } => {
+                self.compile_expression(value)?;
+                self.emit(Opcode::Pop, &[], span)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile the statements of a block so that the value of the block is left on the stack.
+    fn compile_block_statement(&mut self, block: &BlockStatement) -> Result<(), CompileError> {
+        for statement in block.get_statements() {
+            self.compile_statement(statement)?;
+        }
+
+        if self.last_instruction_is(Opcode::Pop) {
+            self.remove_last_instruction();
+        } else {
+            self.emit(Opcode::Null, &[], block.get_span())?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        let span = expression.get_span();
+
+        match expression {
+            Expression::Identifier(identifier) => {
+                let symbol = match self.symbol_table.resolve(identifier.get_value()) {
+                    Some(symbol) => symbol,
+                    // might be bound later on, otherwise the vm falls back to a builtin of the same
+                    // name or reports it when it is read
+                    None => self.symbol_table.define_global(identifier.get_value()),
+                };
+                self.load_symbol(&symbol, span)?;
+            }
+            Expression::Integer { value, .. } => {
+                let index = self.add_constant(Constant::Integer(*value));
+                self.emit(Opcode::Constant, &[index], span)?;
+            }
+            Expression::Float { value, .. } => {
+                let index = self.add_constant(Constant::Float(*value));
+                self.emit(Opcode::Constant, &[index], span)?;
+            }
+            Expression::String { value, .. } => {
+                let index = self.add_constant(Constant::String(value.clone()));
+                self.emit(Opcode::Constant, &[index], span)?;
+            }
+            Expression::Boolean { value: true, .. } => {
+                self.emit(Opcode::True, &[], span)?;
+            }
+            Expression::Boolean { value: false, .. } => {
+                self.emit(Opcode::False, &[], span)?;
+            }
+            Expression::Prefix {
+                operator, right, ..
+            } => {
+                self.compile_expression(right)?;
+                let op = match operator.as_str() {
+                    "!" => Opcode::Bang,
+                    "-" => Opcode::Minus,
+                    _ => {
+                        return Err(CompileError::new(
+                            format!("unknown operator: {}", operator),
+                            span,
+                        ))
+                    }
+                };
+                self.emit(op, &[], span)?;
+            }
+            Expression::Infix {
+                left,
+                operator,
+                right,
+                ..
+            } if operator == "&&" || operator == "||" => {
+                self.compile_logical_expression(left, operator, right, span)?;
+            }
+            Expression::Infix {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+
+                let op = match operator.as_str() {
+                    "+" => Opcode::Add,
+                    "-" => Opcode::Sub,
+                    "*" => Opcode::Mul,
+                    "/" => Opcode::Div,
+                    "%" => Opcode::Mod,
+                    "==" => Opcode::Equal,
+                    "!=" => Opcode::NotEqual,
+                    "<" => Opcode::LessThan,
+                    ">" => Opcode::GreaterThan,
+                    "<=" => Opcode::LessEqual,
+                    ">=" => Opcode::GreaterEqual,
+                    _ => {
+                        return Err(CompileError::new(
+                            format!("unknown operator: {}", operator),
+                            span,
+                        ))
+                    }
+                };
+                self.emit(op, &[], span)?;
+            }
+            Expression::Grouped { expression, .. } => {
+                self.compile_expression(expression)?;
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0], span)?;
+
+                self.compile_block_statement(consequence)?;
+                let jump = self.emit(Opcode::Jump, &[0], span)?;
+
+                self.patch_jump(jump_not_truthy, span)?;
+                match alternative {
+                    Some(alternative) => self.compile_block_statement(alternative)?,
+                    None => {
+                        self.emit(Opcode::Null, &[], span)?;
+                    }
+                }
+                self.patch_jump(jump, span)?;
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                self.compile_function_literal(parameters, body, span)?;
+            }
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                self.compile_expression(function)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                self.emit(Opcode::Call, &[arguments.len()], span)?;
+            }
+            Expression::Array { elements, .. } => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[elements.len()], span)?;
+            }
+            Expression::Hash { pairs, .. } => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    // checked before the value is evaluated, at the key like in the evaluator
+                    self.emit(Opcode::HashKey, &[], key.get_span())?;
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[pairs.len()], span)?;
+            }
+            Expression::Index { left, index, .. } => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Opcode::Index, &[], span)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `&&` and `||` only evaluate the right side if it can still change the result, which is
+    /// always a boolean.
+    fn compile_logical_expression(
+        &mut self,
+        left: &Expression,
+        operator: &str,
+        right: &Expression,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        self.compile_expression(left)?;
+        let mut jumps_to_false = Vec::new();
+        let mut jumps_to_end = Vec::new();
+
+        if operator == "&&" {
+            jumps_to_false.push(self.emit(Opcode::JumpNotTruthy, &[0], span)?);
+        } else {
+            let jump_to_right = self.emit(Opcode::JumpNotTruthy, &[0], span)?;
+            self.emit(Opcode::True, &[], span)?;
+            jumps_to_end.push(self.emit(Opcode::Jump, &[0], span)?);
+            self.patch_jump(jump_to_right, span)?;
+        }
+
+        self.compile_expression(right)?;
+        jumps_to_false.push(self.emit(Opcode::JumpNotTruthy, &[0], span)?);
+        self.emit(Opcode::True, &[], span)?;
+        jumps_to_end.push(self.emit(Opcode::Jump, &[0], span)?);
+
+        for jump in jumps_to_false {
+            self.patch_jump(jump, span)?;
+        }
+        self.emit(Opcode::False, &[], span)?;
+
+        for jump in jumps_to_end {
+            self.patch_jump(jump, span)?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_function_literal(
+        &mut self,
+        parameters: &[Identifier],
+        body: &BlockStatement,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        self.enter_scope();
+
+        for parameter in parameters {
+            self.symbol_table.define_parameter(parameter.get_value());
+        }
+
+        // every call has one environment in the evaluator, so every `let` of the body is a local
+        // from the start and closures defined before it already share it
+        let mut names = Vec::new();
+        collect_let_names(body.get_statements(), &mut names);
+        names.retain(|name| parameters.iter().all(|p| p.get_value() != *name));
+
+        let mut fallbacks = Vec::new();
+        for name in names {
+            let symbol = match self.symbol_table.resolve_outer(name) {
+                Some(symbol) => symbol,
+                None => self.symbol_table.define_global(name),
+            };
+            fallbacks.push(origin(&symbol));
+            self.symbol_table.define(name);
+        }
+
+        for statement in body.get_statements() {
+            self.compile_statement(statement)?;
+        }
+
+        // the value of the last expression is returned implicitly
+        if self.last_instruction_is(Opcode::Pop) {
+            let (_, position) = self.current_scope().last_instruction.unwrap();
+            self.current_scope().instructions[position] = Opcode::ReturnValue as u8;
+            self.current_scope().last_instruction = Some((Opcode::ReturnValue, position));
+        }
+        if !self.last_instruction_is(Opcode::ReturnValue) {
+            self.emit(Opcode::Return, &[], body.get_span())?;
+        }
+
+        let (scope, free_symbols, num_locals) = self.leave_scope();
+
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            spans: scope.spans,
+            num_locals,
+            parameters: parameters.iter().map(|p| p.get_value().clone()).collect(),
+            free: free_symbols.iter().map(origin).collect(),
+            fallbacks,
+        };
+        let index = self.add_constant(Constant::Function(Rc::new(function)));
+        self.emit(Opcode::Closure, &[index], span)?;
+
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, span: Span) -> Result<usize, CompileError> {
+        let index = symbol.get_index();
+        match symbol.get_scope() {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[index], span),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[index], span),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[index], span),
+        }
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    /// Add an instruction to the current scope and return its offset.
+    fn emit(&mut self, op: Opcode, operands: &[usize], span: Span) -> Result<usize, CompileError> {
+        let instruction = make(op, operands).ok_or_else(|| {
+            CompileError::new(
+                format!(
+                    "program too large, operand of {} out of range",
+                    op.get_name()
+                ),
+                span,
+            )
+        })?;
+
+        let scope = self.current_scope();
+        let position = scope.instructions.len();
+        scope.instructions.extend(instruction);
+        scope.spans.push((position, span));
+        scope.last_instruction = Some((op, position));
+
+        Ok(position)
+    }
+
+    /// Point the jump at `position` to the end of the current instructions.
+    fn patch_jump(&mut self, position: usize, span: Span) -> Result<(), CompileError> {
+        let target = self.current_scope().instructions.len();
+        let op = Opcode::from_byte(self.current_scope().instructions[position])
+            .expect("only emitted jumps are patched");
+        let instruction = make(op, &[target]).ok_or_else(|| {
+            CompileError::new("program too large, jump target out of range".into(), span)
+        })?;
+
+        let instructions = &mut self.current_scope().instructions;
+        instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+
+        Ok(())
+    }
+
+    fn last_instruction_is(&mut self, op: Opcode) -> bool {
+        matches!(self.current_scope().last_instruction, Some((last, _)) if last == op)
+    }
+
+    /// Only used to drop a trailing `Pop`, so the previous instruction is not tracked.
+    fn remove_last_instruction(&mut self) {
+        let scope = self.current_scope();
+        if let Some((_, position)) = scope.last_instruction.take() {
+            scope.instructions.truncate(position);
+            scope.spans.pop();
+        }
+    }
+
+    fn current_scope(&mut self) -> &mut CompilationScope {
+        self.scopes
+            .last_mut()
+            .expect("the main scope is never left")
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> (CompilationScope, Vec<Symbol>, usize) {
+        let scope = self.scopes.pop().expect("only entered scopes are left");
+        let (outer, free_symbols, num_locals) = std::mem::take(&mut self.symbol_table).into_outer();
+        self.symbol_table = outer;
+
+        (scope, free_symbols, num_locals)
+    }
+}
+
+fn origin(symbol: &Symbol) -> Origin {
+    let index = symbol.get_index();
+    match symbol.get_scope() {
+        SymbolScope::Global => Origin::Global(index),
+        SymbolScope::Local => Origin::Local(index),
+        SymbolScope::Free => Origin::Free(index),
+    }
+}
+
+/// Names bound with `let` in the statements, including nested blocks but not other functions.
+fn collect_let_names<'a>(statements: &'a [Statement], names: &mut Vec<&'a String>) {
+    for statement in statements {
+        let value = match statement {
+            Statement::Let { name, value, .. } => {
+                if !names.contains(&name.get_value()) {
+                    names.push(name.get_value());
+                }
+                value
+            }
+            Statement::Return { value, .. } | Statement::Expression { value, .. } => value,
+        };
+        collect_expression_let_names(value, names);
+    }
+}
+
+fn collect_expression_let_names<'a>(expression: &'a Expression, names: &mut Vec<&'a String>) {
+    match expression {
+        Expression::Prefix { right, .. } => collect_expression_let_names(right, names),
+        Expression::Infix { left, right, .. }
+        | Expression::Index {
+            left, index: right, ..
+        } => {
+            collect_expression_let_names(left, names);
+            collect_expression_let_names(right, names);
+        }
+        Expression::Grouped { expression, .. } => collect_expression_let_names(expression, names),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            collect_expression_let_names(condition, names);
+            collect_let_names(consequence.get_statements(), names);
+            if let Some(alternative) = alternative {
+                collect_let_names(alternative.get_statements(), names);
+            }
+        }
+        Expression::Call {
+            function,
+            arguments: elements,
+            ..
+        } => {
+            collect_expression_let_names(function, names);
+            for element in elements {
+                collect_expression_let_names(element, names);
+            }
+        }
+        Expression::Array { elements, .. } => {
+            for element in elements {
+                collect_expression_let_names(element, names);
+            }
+        }
+        Expression::Hash { pairs, .. } => {
+            for (key, value) in pairs {
+                collect_expression_let_names(key, names);
+                collect_expression_let_names(value, names);
+            }
+        }
+        // the body of a function literal has its own environment
+        _ => {}
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl CompiledFunction {
+    pub fn get_instructions(&self) -> &Vec<u8> {
+        &self.instructions
+    }
+
+    pub fn get_num_locals(&self) -> usize {
+        self.num_locals
+    }
+
+    pub fn get_parameters(&self) -> &Vec<String> {
+        &self.parameters
+    }
+
+    pub fn get_free(&self) -> &Vec<Origin> {
+        &self.free
+    }
+
+    pub fn get_fallbacks(&self) -> &Vec<Origin> {
+        &self.fallbacks
+    }
+
+    /// Span of the source the instruction at `offset` was compiled from.
+    pub fn get_span(&self, offset: usize) -> Span {
+        match self
+            .spans
+            .binary_search_by_key(&offset, |(position, _)| *position)
+        {
+            Ok(i) => self.spans[i].1,
+            Err(i) => self
+                .spans
+                .get(i.saturating_sub(1))
+                .map_or(Span::default(), |s| s.1),
+        }
+    }
+}
+
+impl Bytecode {
+    pub fn get_main(&self) -> &Rc<CompiledFunction> {
+        &self.main
+    }
+
+    pub fn get_constants(&self) -> &Vec<Constant> {
+        &self.constants
+    }
+
+    pub fn get_global_names(&self) -> &Vec<String> {
+        &self.global_names
+    }
+
+    /// 1-based line of an offset in the source the program was compiled from.
+    pub fn get_line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line + 1,
+            Err(next_line) => next_line.max(1),
+        }
+    }
+}
+
+impl CompileError {
+    pub fn new(message: String, span: Span) -> CompileError {
+        CompileError { message, span }
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.message.clone(), self.span)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Integer(value) => write!(f, "{}", value),
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::String(value) => write!(f, "{:?}", value),
+            Constant::Function(function) => {
+                write!(f, "fn({})", function.parameters.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", disassemble(self, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, format_instructions, make, Bytecode, Constant, Opcode, Origin};
+    use crate::{lexer::Lexer, parser::Parser, span::Span};
+
+    fn compile_input(input: &str) -> Bytecode {
+        let program = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .expect("Program could not be parsed correctly");
+
+        compile(&program, input).expect("Program could not be compiled")
+    }
+
+    fn instructions(instructions: &[(Opcode, &[usize])]) -> String {
+        let bytes: Vec<u8> = instructions
+            .iter()
+            .flat_map(|(op, operands)| make(*op, operands).unwrap())
+            .collect();
+
+        format_instructions(&bytes)
+    }
+
+    fn function_constant(bytecode: &Bytecode, index: usize) -> String {
+        match &bytecode.get_constants()[index] {
+            Constant::Function(function) => format_instructions(function.get_instructions()),
+            constant => panic!("Expected a function, got {:?}", constant),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let bytecode = compile_input("1 + 2 * 3; -1");
+
+        assert_eq!(
+            format_instructions(bytecode.get_main().get_instructions()),
+            instructions(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Constant, &[2]),
+                (Opcode::Mul, &[]),
+                (Opcode::Add, &[]),
+                (Opcode::Pop, &[]),
+                (Opcode::Constant, &[3]),
+                (Opcode::Minus, &[]),
+                (Opcode::Pop, &[]),
+            ])
+        );
+        assert_eq!(
+            bytecode.get_constants(),
+            &vec![
+                Constant::Integer(1),
+                Constant::Integer(2),
+                Constant::Integer(3),
+                Constant::Integer(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let bytecode = compile_input("if (true) { 10 }; 3333;");
+
+        assert_eq!(
+            format_instructions(bytecode.get_main().get_instructions()),
+            instructions(&[
+                (Opcode::True, &[]),
+                (Opcode::JumpNotTruthy, &[10]),
+                (Opcode::Constant, &[0]),
+                (Opcode::Jump, &[11]),
+                (Opcode::Null, &[]),
+                (Opcode::Pop, &[]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Pop, &[]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let bytecode = compile_input("let one = 1; let two = one;");
+
+        assert_eq!(
+            format_instructions(bytecode.get_main().get_instructions()),
+            instructions(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::SetGlobal, &[1]),
+                (Opcode::Null, &[]),
+                (Opcode::Pop, &[]),
+            ])
+        );
+        assert_eq!(bytecode.get_global_names(), &vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_closures() {
+        let bytecode = compile_input("fn(a) { fn(b) { a + b + len } }");
+
+        assert_eq!(
+            function_constant(&bytecode, 0),
+            instructions(&[
+                (Opcode::GetFree, &[0]),
+                (Opcode::GetLocal, &[0]),
+                (Opcode::Add, &[]),
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::Add, &[]),
+                (Opcode::ReturnValue, &[]),
+            ])
+        );
+        assert_eq!(
+            function_constant(&bytecode, 1),
+            instructions(&[(Opcode::Closure, &[0]), (Opcode::ReturnValue, &[]),])
+        );
+        assert_eq!(
+            format_instructions(bytecode.get_main().get_instructions()),
+            instructions(&[(Opcode::Closure, &[1]), (Opcode::Pop, &[])])
+        );
+
+        let Constant::Function(inner) = &bytecode.get_constants()[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(inner.get_free(), &vec![Origin::Local(0)]);
+        assert_eq!(bytecode.get_global_names(), &vec!["len"]);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let bytecode = compile_input("let f = fn(x) { f(x - 1) }; fn() { let g = fn() { g() } }");
+
+        assert_eq!(
+            function_constant(&bytecode, 1),
+            instructions(&[
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::GetLocal, &[0]),
+                (Opcode::Constant, &[0]),
+                (Opcode::Sub, &[]),
+                (Opcode::Call, &[1]),
+                (Opcode::ReturnValue, &[]),
+            ])
+        );
+        assert_eq!(
+            function_constant(&bytecode, 2),
+            instructions(&[
+                (Opcode::GetFree, &[0]),
+                (Opcode::Call, &[0]),
+                (Opcode::ReturnValue, &[])
+            ])
+        );
+        assert_eq!(
+            function_constant(&bytecode, 3),
+            instructions(&[
+                (Opcode::Closure, &[2]),
+                (Opcode::SetLocal, &[0]),
+                (Opcode::Return, &[]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_locals_are_declared_for_the_whole_function() {
+        let bytecode =
+            compile_input("let x = 1; fn(a) { let f = fn() { x + len }; let x = a; let len = a; }");
+
+        let Constant::Function(inner) = &bytecode.get_constants()[1] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(inner.get_free(), &vec![Origin::Local(2), Origin::Local(3)]);
+
+        let Constant::Function(outer) = &bytecode.get_constants()[2] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(outer.get_num_locals(), 4);
+        assert_eq!(
+            outer.get_fallbacks(),
+            &vec![Origin::Global(1), Origin::Global(0), Origin::Global(2)]
+        );
+        assert_eq!(bytecode.get_global_names(), &vec!["x", "f", "len"]);
+    }
+
+    #[test]
+    fn test_forward_references_are_globals() {
+        let bytecode = compile_input("let f = fn() { g() }; let g = fn() { 1 };");
+
+        assert_eq!(
+            function_constant(&bytecode, 0),
+            instructions(&[
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::Call, &[0]),
+                (Opcode::ReturnValue, &[])
+            ])
+        );
+        assert_eq!(bytecode.get_global_names(), &vec!["g", "f"]);
+    }
+
+    #[test]
+    fn test_hash_keys_are_checked_before_the_value() {
+        let bytecode = compile_input("{1: 2}");
+        let main = bytecode.get_main();
+
+        assert_eq!(
+            format_instructions(main.get_instructions()),
+            instructions(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::HashKey, &[]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Hash, &[1]),
+                (Opcode::Pop, &[]),
+            ])
+        );
+        assert_eq!(main.get_span(3), Span::new(1, 2));
+    }
+
+    #[test]
+    fn test_spans() {
+        let bytecode = compile_input("let x = 1;\nx + true;");
+        let main = bytecode.get_main();
+
+        // OpConstant, OpSetGlobal, OpGetGlobal, OpTrue, OpAdd
+        assert_eq!(main.get_span(0), Span::new(8, 9));
+        assert_eq!(main.get_span(3), Span::new(0, 9));
+        assert_eq!(main.get_span(6), Span::new(11, 12));
+        assert_eq!(main.get_span(10), Span::new(11, 19));
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::Write;
+
+/// Instructions are encoded as one opcode byte followed by its operands in big endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    True,
+    False,
+    Null,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    LessEqual,
+    GreaterEqual,
+    Minus,
+    Bang,
+    Jump,
+    JumpNotTruthy,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GetFree,
+    Closure,
+    Array,
+    Hash,
+    HashKey,
+    Index,
+    Call,
+    ReturnValue,
+    Return,
+}
+
+const OPCODES: [Opcode; 33] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Mod,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::LessThan,
+    Opcode::GreaterThan,
+    Opcode::LessEqual,
+    Opcode::GreaterEqual,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::Jump,
+    Opcode::JumpNotTruthy,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::Closure,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::HashKey,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    /// Width in bytes of each operand.
+    pub fn get_operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::Closure => &[2],
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::GetFree | Opcode::Call => &[1],
+            _ => &[],
+        }
+    }
+
+    /// Name as shown in the disassembly, e.g. `OpConstant`.
+    pub fn get_name(self) -> String {
+        format!("Op{:?}", self)
+    }
+}
+
+/// Encode an instruction, returns `None` if an operand does not fit into its width.
+pub fn make(op: Opcode, operands: &[usize]) -> Option<Vec<u8>> {
+    let widths = op.get_operand_widths();
+    let mut instruction = vec![op as u8];
+
+    for (operand, width) in operands.iter().zip(widths) {
+        match width {
+            1 => instruction.push(u8::try_from(*operand).ok()?),
+            2 => instruction.extend(u16::try_from(*operand).ok()?.to_be_bytes()),
+            _ => unreachable!("operands are one or two bytes wide"),
+        }
+    }
+
+    Some(instruction)
+}
+
+/// Decode the operands following an opcode, returns them together with the number of bytes read.
+pub fn read_operands(op: Opcode, bytes: &[u8]) -> ([usize; 1], usize) {
+    let mut operands = [0; 1];
+    let mut offset = 0;
+
+    for (i, width) in op.get_operand_widths().iter().enumerate() {
+        operands[i] = match width {
+            1 => bytes[offset] as usize,
+            _ => u16::from_be_bytes([bytes[offset], bytes[offset + 1]]) as usize,
+        };
+        offset += width;
+    }
+
+    (operands, offset)
+}
+
+/// Print one instruction per line with its offset, e.g. `0003 OpConstant 1`.
+pub fn format_instructions(instructions: &[u8]) -> String {
+    let mut output = String::new();
+    let mut ip = 0;
+
+    while ip < instructions.len() {
+        let Some(op) = Opcode::from_byte(instructions[ip]) else {
+            let _ = writeln!(
+                output,
+                "{:04} ERROR: unknown opcode {}",
+                ip, instructions[ip]
+            );
+            ip += 1;
+            continue;
+        };
+
+        let width: usize = op.get_operand_widths().iter().sum();
+        if ip + 1 + width > instructions.len() {
+            let _ = writeln!(
+                output,
+                "{:04} ERROR: truncated instruction {}",
+                ip,
+                op.get_name()
+            );
+            break;
+        }
+
+        let count = op.get_operand_widths().len();
+        let (operands, read) = read_operands(op, &instructions[ip + 1..]);
+        let _ = write!(output, "{:04} {}", ip, op.get_name());
+        for operand in &operands[..count] {
+            let _ = write!(output, " {}", operand);
+        }
+        output.push('\n');
+
+        ip += 1 + read;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_instructions, make, read_operands, Opcode, OPCODES};
+
+    #[test]
+    fn test_make() {
+        let tests = [
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (
+                Opcode::GetLocal,
+                vec![255],
+                vec![Opcode::GetLocal as u8, 255],
+            ),
+            (
+                Opcode::Closure,
+                vec![65534],
+                vec![Opcode::Closure as u8, 255, 254],
+            ),
+        ];
+
+        for (op, operands, expected) in tests {
+            assert_eq!(make(op, &operands), Some(expected), "op: {:?}", op);
+        }
+
+        assert_eq!(make(Opcode::Constant, &[65536]), None);
+        assert_eq!(make(Opcode::GetLocal, &[256]), None);
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests = [
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Closure, vec![65535], 2),
+        ];
+
+        for (op, operands, bytes_read) in tests {
+            let instruction = make(op, &operands).unwrap();
+            let (read, count) = read_operands(op, &instruction[1..]);
+
+            assert_eq!(count, bytes_read);
+            assert_eq!(&read[..operands.len()], &operands[..]);
+        }
+    }
+
+    #[test]
+    fn test_opcode_bytes() {
+        for (i, op) in OPCODES.iter().enumerate() {
+            assert_eq!(*op as usize, i);
+            assert_eq!(Opcode::from_byte(i as u8), Some(*op));
+        }
+        assert_eq!(Opcode::from_byte(OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    fn test_format_instructions() {
+        let instructions: Vec<u8> = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535]),
+        ]
+        .into_iter()
+        .flat_map(Option::unwrap)
+        .collect();
+
+        assert_eq!(
+            format_instructions(&instructions),
+            "0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpClosure 65535
+"
+        );
+    }
+
+    #[test]
+    fn test_format_truncated_instruction() {
+        let mut instructions = make(Opcode::Add, &[]).unwrap();
+        instructions.extend(make(Opcode::Constant, &[258]).unwrap());
+        instructions.pop();
+
+        assert_eq!(
+            format_instructions(&instructions),
+            "0000 OpAdd\n0001 ERROR: truncated instruction OpConstant\n"
+        );
+        assert_eq!(
+            format_instructions(&[Opcode::Call as u8]),
+            "0000 ERROR: truncated instruction OpCall\n"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use super::{read_operands, Bytecode, CompiledFunction, Constant, Opcode};
+
+/// Print the main program and every compiled function with the offset and operands of each
+/// instruction. The source line an instruction was compiled from is printed above it whenever
+/// the line changes, with its text if the `source` is given:
+///
+/// ```text
+/// == main ==
+///    1 | let x = 1;
+/// 0000 OpConstant 0 (1)
+/// 0003 OpSetGlobal 0 (x)
+/// ```
+pub fn disassemble(bytecode: &Bytecode, source: Option<&str>) -> String {
+    let mut output = String::from("== main ==\n");
+    disassemble_function(&mut output, bytecode, bytecode.get_main(), source);
+
+    for (i, constant) in bytecode.get_constants().iter().enumerate() {
+        if let Constant::Function(function) = constant {
+            let _ = writeln!(output, "== constant {}: {} ==", i, constant);
+            disassemble_function(&mut output, bytecode, function, source);
+        }
+    }
+
+    output
+}
+
+fn disassemble_function(
+    output: &mut String,
+    bytecode: &Bytecode,
+    function: &CompiledFunction,
+    source: Option<&str>,
+) {
+    let instructions = function.get_instructions();
+    let mut ip = 0;
+    let mut last_line = None;
+
+    while ip < instructions.len() {
+        let line = bytecode.get_line(function.get_span(ip).get_start());
+        if last_line != Some(line) {
+            let text = source.and_then(|s| s.lines().nth(line - 1)).unwrap_or("");
+            let header = format!("{:>4} | {}", line, text.trim());
+            let _ = writeln!(output, "{}", header.trim_end());
+            last_line = Some(line);
+        }
+
+        let Some(op) = Opcode::from_byte(instructions[ip]) else {
+            let _ = writeln!(
+                output,
+                "{:04} ERROR: unknown opcode {}",
+                ip, instructions[ip]
+            );
+            ip += 1;
+            continue;
+        };
+
+        let width: usize = op.get_operand_widths().iter().sum();
+        if ip + 1 + width > instructions.len() {
+            let _ = writeln!(
+                output,
+                "{:04} ERROR: truncated instruction {}",
+                ip,
+                op.get_name()
+            );
+            break;
+        }
+
+        let (operands, read) = read_operands(op, &instructions[ip + 1..]);
+        let operands = &operands[..op.get_operand_widths().len()];
+
+        let _ = write!(output, "{:04} {}", ip, op.get_name());
+        for operand in operands {
+            let _ = write!(output, " {}", operand);
+        }
+        if let Some(comment) = describe_operand(bytecode, function, op, operands) {
+            let _ = write!(output, " ({})", comment);
+        }
+        output.push('\n');
+
+        ip += 1 + read;
+    }
+}
+
+/// What the first operand refers to, e.g. the value of a constant or the name of a global.
+fn describe_operand(
+    bytecode: &Bytecode,
+    function: &CompiledFunction,
+    op: Opcode,
+    operands: &[usize],
+) -> Option<String> {
+    let operand = *operands.first()?;
+
+    match op {
+        Opcode::Constant | Opcode::Closure => bytecode
+            .get_constants()
+            .get(operand)
+            .map(|constant| constant.to_string()),
+        Opcode::GetGlobal | Opcode::SetGlobal => bytecode.get_global_names().get(operand).cloned(),
+        Opcode::GetLocal | Opcode::SetLocal => function.get_parameters().get(operand).cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+    use crate::{compiler::compile, lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn test_disassemble() {
+        let input = "let x = 1;\nlet f = fn(a) {\n  a + len(\"x\")\n};\nf(x)";
+        let program = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .expect("Program could not be parsed correctly");
+        let bytecode = compile(&program, input).expect("Program could not be compiled");
+
+        let expected = r#"== main ==
+   1 | let x = 1;
+0000 OpConstant 0 (1)
+0003 OpSetGlobal 0 (x)
+   2 | let f = fn(a) {
+0006 OpClosure 2 (fn(a))
+0009 OpSetGlobal 2 (f)
+   5 | f(x)
+0012 OpGetGlobal 2 (f)
+0015 OpGetGlobal 0 (x)
+0018 OpCall 1
+0020 OpPop
+== constant 2: fn(a) ==
+   3 | a + len("x")
+0000 OpGetLocal 0 (a)
+0002 OpGetGlobal 1 (len)
+0005 OpConstant 1 ("x")
+0008 OpCall 1
+0010 OpAdd
+0011 OpReturnValue
+"#;
+        assert_eq!(disassemble(&bytecode, Some(input)), expected);
+
+        let without_source = disassemble(&bytecode, None);
+        assert!(without_source.contains("\n   1 |\n0000 OpConstant 0 (1)\n"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Free,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    name: String,
+    scope: SymbolScope,
+    index: usize,
+}
+
+/// Bindings of one function (or the global scope), names that are not defined locally are looked
+/// up in the enclosing tables and captured as free variables if they are locals there.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+    global_names: Vec<String>,
+}
+
+impl Symbol {
+    pub fn get_scope(&self) -> SymbolScope {
+        self.scope
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        Default::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..Default::default()
+        }
+    }
+
+    /// Give back the enclosing table, together with the free variables captured by this one.
+    pub fn into_outer(self) -> (SymbolTable, Vec<Symbol>, usize) {
+        let outer = self.outer.expect("only enclosed tables are left");
+        (*outer, self.free_symbols, self.num_definitions)
+    }
+
+    /// Define a binding in this scope, defining a name again reuses its slot just like the
+    /// evaluator overwrites the binding.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+
+        if let Some(symbol) = self.store.get(name) {
+            if symbol.scope == scope {
+                return symbol.clone();
+            }
+        }
+
+        let symbol = Symbol {
+            name: name.into(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        if scope == SymbolScope::Global {
+            self.global_names.push(name.into());
+        }
+
+        self.store.insert(name.into(), symbol.clone());
+        symbol
+    }
+
+    /// Define a name in the outermost scope, used for names that are only bound later on.
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match &mut self.outer {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    /// Parameters always get a slot of their own, a repeated name refers to the last one like in
+    /// the evaluator.
+    pub fn define_parameter(&mut self, name: &str) -> Symbol {
+        self.store.remove(name);
+        self.define(name)
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    /// Resolve a name in the enclosing tables only, which is what a local reads before it is
+    /// bound. A local of an enclosing function is captured without shadowing the local here.
+    pub fn resolve_outer(&mut self, name: &str) -> Option<Symbol> {
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global => Some(symbol),
+            _ => {
+                self.free_symbols.push(symbol);
+                Some(Symbol {
+                    name: name.into(),
+                    scope: SymbolScope::Free,
+                    index: self.free_symbols.len() - 1,
+                })
+            }
+        }
+    }
+
+    /// Names of the globals by their index, to report uninitialized globals.
+    pub fn get_global_names(&self) -> &Vec<String> {
+        match &self.outer {
+            Some(outer) => outer.get_global_names(),
+            None => &self.global_names,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Symbol, SymbolScope, SymbolTable};
+
+    fn symbol(name: &str, scope: SymbolScope, index: usize) -> Symbol {
+        Symbol {
+            name: name.into(),
+            scope,
+            index,
+        }
+    }
+
+    #[test]
+    fn test_define_and_resolve() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+        assert_eq!(global.define("b"), symbol("b", SymbolScope::Global, 1));
+        assert_eq!(global.define("a"), symbol("a", SymbolScope::Global, 0));
+
+        let mut local = SymbolTable::new_enclosed(global);
+        assert_eq!(local.define("c"), symbol("c", SymbolScope::Local, 0));
+        assert_eq!(local.define("b"), symbol("b", SymbolScope::Local, 1));
+
+        assert_eq!(
+            local.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(local.resolve("b"), Some(symbol("b", SymbolScope::Local, 1)));
+        assert_eq!(local.resolve("c"), Some(symbol("c", SymbolScope::Local, 0)));
+        assert_eq!(local.resolve("d"), None);
+    }
+
+    #[test]
+    fn test_resolve_free() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define("b");
+
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define("c");
+
+        assert_eq!(
+            second.resolve("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(second.resolve("b"), Some(symbol("b", SymbolScope::Free, 0)));
+        assert_eq!(
+            second.resolve("c"),
+            Some(symbol("c", SymbolScope::Local, 0))
+        );
+
+        let (first, free, num_definitions) = second.into_outer();
+        assert_eq!(free, vec![symbol("b", SymbolScope::Local, 0)]);
+        assert_eq!(num_definitions, 1);
+        assert_eq!(first.get_global_names(), &vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_define_parameter() {
+        let mut local = SymbolTable::new_enclosed(SymbolTable::new());
+
+        assert_eq!(
+            local.define_parameter("a"),
+            symbol("a", SymbolScope::Local, 0)
+        );
+        assert_eq!(
+            local.define_parameter("a"),
+            symbol("a", SymbolScope::Local, 1)
+        );
+        assert_eq!(local.resolve("a"), Some(symbol("a", SymbolScope::Local, 1)));
+    }
+
+    #[test]
+    fn test_resolve_outer() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define("b");
+
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define("b");
+
+        assert_eq!(
+            second.resolve_outer("a"),
+            Some(symbol("a", SymbolScope::Global, 0))
+        );
+        assert_eq!(
+            second.resolve_outer("b"),
+            Some(symbol("b", SymbolScope::Free, 0))
+        );
+        assert_eq!(
+            second.resolve("b"),
+            Some(symbol("b", SymbolScope::Local, 0))
+        );
+        assert_eq!(second.resolve_outer("c"), None);
+
+        let (_, free, _) = second.into_outer();
+        assert_eq!(free, vec![symbol("b", SymbolScope::Local, 0)]);
+    }
+
+    #[test]
+    fn test_define_global_from_nested_scope() {
+        let mut local = SymbolTable::new_enclosed(SymbolTable::new());
+
+        assert_eq!(
+            local.define_global("g"),
+            symbol("g", SymbolScope::Global, 0)
+        );
+        assert_eq!(
+            local.resolve("g"),
+            Some(symbol("g", SymbolScope::Global, 0))
+        );
+    }
+}
@@ -0,0 +1,683 @@
+//! Serialized bytecode, stored in `.tbc` files. Numbers are big-endian like the operands of the
+//! instructions, lengths and offsets are four bytes wide:
+//!
+//! ```text
+//! magic        \x7fTBC
+//! version      u16
+//! line table   count, offset where each line of the source starts
+//! globals      count, names
+//! constants    count, tag byte followed by the value
+//! main         function
+//! ```
+//!
+//! Strings are stored as their length followed by the utf-8 bytes, functions as the number of
+//! locals, the parameter names, the captured variables, the fallbacks of the other locals, the
+//! instructions and the span of each instruction. Variables are a tag byte followed by their
+//! index.
+
+use std::{fmt, rc::Rc};
+
+use super::{read_operands, Bytecode, CompiledFunction, Constant, Opcode, Origin};
+use crate::span::Span;
+
+pub const MAGIC: [u8; 4] = *b"\x7fTBC";
+pub const VERSION: u16 = 2;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_FLOAT: u8 = 1;
+const TAG_STRING: u8 = 2;
+const TAG_FUNCTION: u8 = 3;
+
+const TAG_LOCAL: u8 = 0;
+const TAG_FREE: u8 = 1;
+const TAG_GLOBAL: u8 = 2;
+
+/// Reason why a bytecode file could not be loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    message: String,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Bytecode {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = MAGIC.to_vec();
+        output.extend(VERSION.to_be_bytes());
+
+        write_length(&mut output, self.line_starts.len());
+        for line_start in &self.line_starts {
+            write_length(&mut output, *line_start);
+        }
+
+        write_length(&mut output, self.global_names.len());
+        for name in &self.global_names {
+            write_string(&mut output, name);
+        }
+
+        write_length(&mut output, self.constants.len());
+        for constant in &self.constants {
+            match constant {
+                Constant::Integer(value) => {
+                    output.push(TAG_INTEGER);
+                    output.extend(value.to_be_bytes());
+                }
+                Constant::Float(value) => {
+                    output.push(TAG_FLOAT);
+                    output.extend(value.to_bits().to_be_bytes());
+                }
+                Constant::String(value) => {
+                    output.push(TAG_STRING);
+                    write_string(&mut output, value);
+                }
+                Constant::Function(function) => {
+                    output.push(TAG_FUNCTION);
+                    write_function(&mut output, function);
+                }
+            }
+        }
+
+        write_function(&mut output, &self.main);
+        output
+    }
+
+    /// Load bytecode written by [`Bytecode::to_bytes`], the instructions are checked so that the
+    /// vm cannot read past its stack, constants, globals, variables or instructions.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, DecodeError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(DecodeError::new(
+                "not a bytecode file, the header is missing".into(),
+            ));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            position: MAGIC.len(),
+        };
+
+        let version = u16::from_be_bytes(reader.read_array()?);
+        if version != VERSION {
+            return Err(DecodeError::new(format!(
+                "unsupported bytecode version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let mut line_starts = Vec::new();
+        for _ in 0..reader.read_length()? {
+            line_starts.push(reader.read_length()?);
+        }
+
+        let mut global_names = Vec::new();
+        for _ in 0..reader.read_length()? {
+            global_names.push(reader.read_string()?);
+        }
+
+        let mut constants = Vec::new();
+        for _ in 0..reader.read_length()? {
+            let constant = match reader.read_array::<1>()?[0] {
+                TAG_INTEGER => Constant::Integer(i64::from_be_bytes(reader.read_array()?)),
+                TAG_FLOAT => {
+                    Constant::Float(f64::from_bits(u64::from_be_bytes(reader.read_array()?)))
+                }
+                TAG_STRING => Constant::String(reader.read_string()?),
+                TAG_FUNCTION => Constant::Function(Rc::new(reader.read_function()?)),
+                tag => return Err(DecodeError::new(format!("unknown constant tag {}", tag))),
+            };
+            constants.push(constant);
+        }
+
+        let main = reader.read_function()?;
+        if reader.position != bytes.len() {
+            return Err(DecodeError::new(
+                "unexpected bytes after the program".into(),
+            ));
+        }
+
+        let bytecode = Bytecode {
+            main: Rc::new(main),
+            constants,
+            global_names,
+            line_starts,
+        };
+
+        verify_function(&bytecode, &bytecode.main, true)?;
+        for constant in &bytecode.constants {
+            if let Constant::Function(function) = constant {
+                verify_function(&bytecode, function, false)?;
+            }
+        }
+
+        Ok(bytecode)
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, length: usize) {
+    let length = u32::try_from(length).expect("programs are smaller than 4 GiB");
+    output.extend(length.to_be_bytes());
+}
+
+fn write_string(output: &mut Vec<u8>, value: &str) {
+    write_length(output, value.len());
+    output.extend(value.as_bytes());
+}
+
+fn write_function(output: &mut Vec<u8>, function: &CompiledFunction) {
+    write_length(output, function.num_locals);
+
+    write_length(output, function.parameters.len());
+    for parameter in &function.parameters {
+        write_string(output, parameter);
+    }
+
+    for origins in [&function.free, &function.fallbacks] {
+        write_length(output, origins.len());
+        for origin in origins {
+            let (tag, index) = match *origin {
+                Origin::Local(index) => (TAG_LOCAL, index),
+                Origin::Free(index) => (TAG_FREE, index),
+                Origin::Global(index) => (TAG_GLOBAL, index),
+            };
+            output.push(tag);
+            write_length(output, index);
+        }
+    }
+
+    write_length(output, function.instructions.len());
+    output.extend(&function.instructions);
+
+    write_length(output, function.spans.len());
+    for (offset, span) in &function.spans {
+        write_length(output, *offset);
+        write_length(output, span.get_start());
+        write_length(output, span.get_end());
+    }
+}
+
+/// Check that the vm can run the function without reading past its stack, constants, globals,
+/// variables or instructions. Every instruction has to be complete with operands that point to
+/// something that exists, jumps have to land on a later instruction and every path through the
+/// function has to leave the stack at the same height. Only the main program runs off its end.
+fn verify_function(
+    bytecode: &Bytecode,
+    function: &CompiledFunction,
+    is_main: bool,
+) -> Result<(), DecodeError> {
+    let invalid =
+        |reason: &str, offset: usize| DecodeError::new(format!("{} at offset {}", reason, offset));
+
+    if is_main && function.num_locals + function.free.len() > 0 {
+        return Err(DecodeError::new(
+            "the main program cannot have locals or free variables".into(),
+        ));
+    }
+    let valid_fallback = |origin: &Origin| match *origin {
+        Origin::Free(index) => index < function.free.len(),
+        Origin::Global(index) => index < bytecode.global_names.len(),
+        Origin::Local(_) => false,
+    };
+    if !function.fallbacks.iter().all(valid_fallback) {
+        return Err(DecodeError::new(
+            "a local falls back to a variable that does not exist".into(),
+        ));
+    }
+
+    // offset, opcode and operand of each instruction
+    let instructions = &function.instructions;
+    let mut decoded = Vec::new();
+    let mut ip = 0;
+
+    while ip < instructions.len() {
+        let op =
+            Opcode::from_byte(instructions[ip]).ok_or_else(|| invalid("unknown opcode", ip))?;
+        let width: usize = op.get_operand_widths().iter().sum();
+        if ip + 1 + width > instructions.len() {
+            return Err(invalid("incomplete instruction", ip));
+        }
+
+        let (operands, _) = read_operands(op, &instructions[ip + 1..]);
+        let valid = match op {
+            Opcode::Constant => matches!(
+                bytecode.constants.get(operands[0]),
+                Some(constant) if !matches!(constant, Constant::Function(_))
+            ),
+            // the closure captures variables of this function
+            Opcode::Closure => match bytecode.constants.get(operands[0]) {
+                Some(Constant::Function(closure)) => {
+                    closure.free.iter().all(|origin| match *origin {
+                        Origin::Local(index) => index < function.num_locals,
+                        Origin::Free(index) => index < function.free.len(),
+                        _ => false,
+                    })
+                }
+                _ => false,
+            },
+            Opcode::GetGlobal | Opcode::SetGlobal => operands[0] < bytecode.global_names.len(),
+            Opcode::GetLocal | Opcode::SetLocal => operands[0] < function.num_locals,
+            Opcode::GetFree => operands[0] < function.free.len(),
+            _ => true,
+        };
+        if !valid {
+            return Err(invalid(
+                &format!("invalid operand of {}", op.get_name()),
+                ip,
+            ));
+        }
+
+        decoded.push((ip, op, operands[0]));
+        ip += 1 + width;
+    }
+
+    // index of the instruction at an offset, the end of the instructions is one past the last
+    let index_of = |offset: usize| match decoded.binary_search_by_key(&offset, |i| i.0) {
+        Ok(index) => Some(index),
+        Err(index) if index == decoded.len() && offset == instructions.len() => Some(index),
+        Err(_) => None,
+    };
+
+    // follow every path with the height of the stack at each instruction
+    let mut heights = vec![None; decoded.len() + 1];
+    let mut pending: Vec<(usize, usize)> = vec![(0, 0)];
+
+    while let Some((index, height)) = pending.pop() {
+        match heights[index] {
+            Some(known) if known == height => continue,
+            Some(_) => {
+                let offset = decoded.get(index).map_or(instructions.len(), |i| i.0);
+                return Err(invalid("the stack height differs between paths", offset));
+            }
+            None => heights[index] = Some(height),
+        }
+
+        let Some(&(offset, op, operand)) = decoded.get(index) else {
+            if !is_main {
+                return Err(invalid("function does not return", instructions.len()));
+            }
+            continue;
+        };
+
+        let (pops, pushes) = stack_effect(op, operand);
+        let height = height
+            .checked_sub(pops)
+            .ok_or_else(|| invalid("stack underflow", offset))?
+            + pushes;
+
+        match op {
+            // there are no loops, so jumps only go forward
+            Opcode::Jump | Opcode::JumpNotTruthy => {
+                let target = index_of(operand)
+                    .filter(|target| *target > index)
+                    .ok_or_else(|| {
+                        invalid(&format!("invalid operand of {}", op.get_name()), offset)
+                    })?;
+                pending.push((target, height));
+                if op == Opcode::JumpNotTruthy {
+                    pending.push((index + 1, height));
+                }
+            }
+            Opcode::ReturnValue | Opcode::Return => {}
+            _ => pending.push((index + 1, height)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of values an instruction takes from the stack and puts onto it.
+fn stack_effect(op: Opcode, operand: usize) -> (usize, usize) {
+    match op {
+        Opcode::Constant
+        | Opcode::True
+        | Opcode::False
+        | Opcode::Null
+        | Opcode::GetGlobal
+        | Opcode::GetLocal
+        | Opcode::GetFree
+        | Opcode::Closure => (0, 1),
+        Opcode::Pop
+        | Opcode::SetGlobal
+        | Opcode::SetLocal
+        | Opcode::JumpNotTruthy
+        | Opcode::ReturnValue => (1, 0),
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::Mod
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::LessThan
+        | Opcode::GreaterThan
+        | Opcode::LessEqual
+        | Opcode::GreaterEqual
+        | Opcode::Index => (2, 1),
+        Opcode::Minus | Opcode::Bang | Opcode::HashKey => (1, 1),
+        Opcode::Jump | Opcode::Return => (0, 0),
+        Opcode::Array => (operand, 1),
+        Opcode::Hash => (operand * 2, 1),
+        Opcode::Call => (operand + 1, 1),
+    }
+}
+
+impl Reader<'_> {
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + N)
+            .ok_or_else(|| DecodeError::new("unexpected end of the bytecode file".into()))?;
+        self.position += N;
+
+        Ok(bytes
+            .try_into()
+            .expect("the slice has the length of the array"))
+    }
+
+    fn read_length(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_be_bytes(self.read_array()?) as usize)
+    }
+
+    fn read_bytes(&mut self) -> Result<&[u8], DecodeError> {
+        let length = self.read_length()?;
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| DecodeError::new("unexpected end of the bytecode file".into()))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn read_string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.read_bytes()?.to_vec())
+            .map_err(|_| DecodeError::new("invalid utf-8 in a string".into()))
+    }
+
+    fn read_function(&mut self) -> Result<CompiledFunction, DecodeError> {
+        let num_locals = self.read_length()?;
+
+        let mut parameters = Vec::new();
+        for _ in 0..self.read_length()? {
+            parameters.push(self.read_string()?);
+        }
+        let free = self.read_origins()?;
+        let fallbacks = self.read_origins()?;
+        if parameters.len() + fallbacks.len() != num_locals {
+            return Err(DecodeError::new(
+                "the parameters and other locals do not add up to the number of locals".into(),
+            ));
+        }
+
+        let instructions = self.read_bytes()?.to_vec();
+
+        let mut spans = Vec::new();
+        for _ in 0..self.read_length()? {
+            let offset = self.read_length()?;
+            let span = Span::new(self.read_length()?, self.read_length()?);
+            spans.push((offset, span));
+        }
+
+        Ok(CompiledFunction {
+            instructions,
+            spans,
+            num_locals,
+            parameters,
+            free,
+            fallbacks,
+        })
+    }
+
+    fn read_origins(&mut self) -> Result<Vec<Origin>, DecodeError> {
+        let mut origins = Vec::new();
+        for _ in 0..self.read_length()? {
+            let tag = self.read_array::<1>()?[0];
+            let index = self.read_length()?;
+            origins.push(match tag {
+                TAG_LOCAL => Origin::Local(index),
+                TAG_FREE => Origin::Free(index),
+                TAG_GLOBAL => Origin::Global(index),
+                tag => return Err(DecodeError::new(format!("unknown variable tag {}", tag))),
+            });
+        }
+
+        Ok(origins)
+    }
+}
+
+impl DecodeError {
+    pub fn new(message: String) -> DecodeError {
+        DecodeError { message }
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{DecodeError, MAGIC, VERSION};
+    use crate::{
+        compiler::{compile, make, Bytecode, CompiledFunction, Constant, Opcode, Origin},
+        lexer::Lexer,
+        parser::Parser,
+        vm::Vm,
+    };
+
+    fn compile_input(input: &str) -> Bytecode {
+        let program = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .expect("Program could not be parsed correctly");
+
+        compile(&program, input).expect("Program could not be compiled")
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytecode = compile_input(
+            "let x = [1, 2.5, \"äöü\"];\nlet f = fn(a, b) { let c = a; fn() { c + b } };\n{true: f(1, 2)()}",
+        );
+        let bytes = bytecode.to_bytes();
+
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(Bytecode::from_bytes(&bytes), Ok(bytecode));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let bytes = compile_input("let x = 1; x").to_bytes();
+
+        let mut other_version = bytes.clone();
+        other_version[4..6].copy_from_slice(&(VERSION + 1).to_be_bytes());
+
+        let tests = [
+            (
+                b"let x = 1;".to_vec(),
+                "not a bytecode file, the header is missing",
+            ),
+            (
+                bytes[..bytes.len() - 3].to_vec(),
+                "unexpected end of the bytecode file",
+            ),
+            (
+                [bytes.as_slice(), &[0]].concat(),
+                "unexpected bytes after the program",
+            ),
+            (other_version, "unsupported bytecode version 3, expected 2"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                Bytecode::from_bytes(&input),
+                Err(DecodeError::new(expected.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_instructions() {
+        let bytecode = compile_input("let x = 1; x");
+        let bytes = bytecode.to_bytes();
+        // the 10 bytes of the main instructions come last, followed by the spans of its 4
+        // instructions
+        let instructions = bytes.len() - 4 - 4 * 12 - 10;
+
+        let tests = [
+            // OpConstant 0 -> OpConstant 5
+            (
+                instructions + 2,
+                5,
+                "invalid operand of OpConstant at offset 0",
+            ),
+            // OpSetGlobal 0 -> OpSetGlobal 1
+            (
+                instructions + 5,
+                1,
+                "invalid operand of OpSetGlobal at offset 3",
+            ),
+            // OpGetGlobal 0 -> unknown opcode
+            (instructions + 6, 255, "unknown opcode at offset 6"),
+        ];
+
+        for (position, byte, expected) in tests {
+            let mut input = bytes.clone();
+            input[position] = byte;
+
+            assert_eq!(
+                Bytecode::from_bytes(&input),
+                Err(DecodeError::new(expected.into()))
+            );
+        }
+    }
+
+    fn function(instructions: &[(Opcode, &[usize])]) -> CompiledFunction {
+        CompiledFunction {
+            instructions: instructions
+                .iter()
+                .flat_map(|(op, operands)| make(*op, operands).unwrap())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unsafe_instructions() {
+        let returns_free = CompiledFunction {
+            free: vec![Origin::Local(0)],
+            ..function(&[(Opcode::GetFree, &[0]), (Opcode::ReturnValue, &[])])
+        };
+        let no_return = function(&[(Opcode::Null, &[])]);
+        let unknown_fallback = CompiledFunction {
+            num_locals: 1,
+            fallbacks: vec![Origin::Global(3)],
+            ..function(&[(Opcode::Return, &[])])
+        };
+
+        let tests = [
+            (
+                function(&[(Opcode::Pop, &[])]),
+                None,
+                "stack underflow at offset 0",
+            ),
+            (
+                function(&[(Opcode::Jump, &[4]), (Opcode::Constant, &[0])]),
+                None,
+                "invalid operand of OpJump at offset 0",
+            ),
+            (
+                function(&[(Opcode::GetFree, &[5])]),
+                None,
+                "invalid operand of OpGetFree at offset 0",
+            ),
+            (
+                function(&[(Opcode::Call, &[3])]),
+                None,
+                "stack underflow at offset 0",
+            ),
+            (
+                function(&[(Opcode::Closure, &[1])]),
+                Some(returns_free),
+                "invalid operand of OpClosure at offset 0",
+            ),
+            (
+                function(&[(Opcode::Closure, &[1])]),
+                Some(no_return),
+                "function does not return at offset 1",
+            ),
+            (
+                function(&[(Opcode::Closure, &[1])]),
+                Some(unknown_fallback),
+                "a local falls back to a variable that does not exist",
+            ),
+            (
+                function(&[
+                    (Opcode::True, &[]),
+                    (Opcode::JumpNotTruthy, &[5]),
+                    (Opcode::Null, &[]),
+                ]),
+                None,
+                "the stack height differs between paths at offset 5",
+            ),
+            (
+                CompiledFunction {
+                    free: vec![Origin::Local(0)],
+                    ..function(&[(Opcode::GetFree, &[0])])
+                },
+                None,
+                "the main program cannot have locals or free variables",
+            ),
+        ];
+
+        for (main, constant, expected) in tests {
+            let mut constants = vec![Constant::Integer(1)];
+            constants.extend(constant.map(|f| Constant::Function(Rc::new(f))));
+            let bytecode = Bytecode {
+                main: Rc::new(main),
+                constants,
+                global_names: vec!["x".into()],
+                line_starts: vec![0],
+            };
+
+            assert_eq!(
+                Bytecode::from_bytes(&bytecode.to_bytes()),
+                Err(DecodeError::new(expected.into())),
+                "expected: {}",
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_patched_files_do_not_crash_the_vm() {
+        let input = "
+            let f = fn(a, b) { let c = [a, b][0]; let g = fn() { c + b }; if (a > 1) { g() } else { -c } };
+            let h = {1: f(1, 2), \"x\": f(2, 3) || false};
+            len([h[1], h[\"x\"]])
+        ";
+        let bytes = compile_input(input).to_bytes();
+
+        for position in 0..bytes.len() {
+            for byte in [0, 1, 3, 17, 255] {
+                let mut input = bytes.clone();
+                input[position] = byte;
+
+                // loading fails or the program runs to a result or a runtime error
+                if let Ok(bytecode) = Bytecode::from_bytes(&input) {
+                    let _ = Vm::new(&bytecode).run();
+                }
+            }
+        }
+    }
+}
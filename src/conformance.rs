@@ -0,0 +1,172 @@
+//! Programs that are run by both the evaluator and the vm, which have to agree on the result or
+//! the error, including its span.
+
+use crate::{
+    compiler::{self, Bytecode},
+    evaluator::{self, Environment, Object, RuntimeError, MAX_CALL_DEPTH},
+    lexer::Lexer,
+    parser::Parser,
+    vm::Vm,
+};
+
+const PROGRAMS: &[&str] = &[
+    // literals and operators
+    "5",
+    "-10 + 2 * 3 - 4 / 2",
+    "7 % 3 + 2.5 * 2",
+    "1 + 0.5",
+    "(5 + 10 * 2 + 15 / 3) * 2 + -10",
+    "\"hello\" + \" \" + \"world\"",
+    "1 < 2 == true",
+    "1 <= 1 && 2 >= 3",
+    "!!5",
+    "!null_value_is_not_defined",
+    "1.5 > 1 || x",
+    "false && undefined",
+    "null_is_truthy_check || false",
+    "3 == 3.0",
+    "\"a\" == \"a\"",
+    "[1, 2] == [1, 2]",
+    // conditionals and bindings
+    "if (1 > 2) { 10 }",
+    "if (1 < 2) { 10 } else { 20 }",
+    "if (false) { 10 } else { let x = 1; }",
+    "let a = 5; let b = a * 2; let a = b + a; a",
+    "let a = 5;",
+    "if (true) { return 1; }; 2",
+    "",
+    // functions and closures
+    "let add = fn(a, b) { a + b }; add(1, add(2, 3))",
+    "let f = fn() { }; f()",
+    "let f = fn() { let x = 1; }; f()",
+    "let f = fn(x) { if (x > 5) { return true; } false }; [f(1), f(10)]",
+    "let f = fn(x) { fn(y) { fn(z) { x * y * z } } }; f(2)(3)(4)",
+    "let counter = fn(x) { if (x > 100) { return x; } counter(x + 1) }; counter(0)",
+    "let fibonacci = fn(n) { if (n < 2) { n } else { fibonacci(n - 1) + fibonacci(n - 2) } }; fibonacci(15)",
+    "let f = fn() { g() }; let g = fn() { 42 }; f()",
+    "let global = 10; let f = fn() { global }; let global = 20; f()",
+    "let apply = fn(f, x) { f(x) }; apply(fn(x) { x * x }, 7)",
+    "fn(x) { x }",
+    "len",
+    "let len = fn(x) { 0 }; len([1, 2, 3])",
+    "let f = fn() { let x = 1; let g = fn() { x }; let x = 2; g() }; f()",
+    "let f = fn() { let a = fn() { b() }; let b = fn() { 1 }; a() }; f()",
+    "let x = 1; let f = fn() { let g = fn() { x }; let before = g(); let x = 2; [before, g()] }; f()",
+    "let f = fn(c) { if (c) { let len = 5; }; len }; [f(true), f(false)]",
+    "let f = fn() { let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } }; count(10) }; f()",
+    "let f = fn(a, a) { a }; f(1, 2)",
+    "let f = fn(x) { let g = fn() { x }; let x = x * 2; g() }; f(3)",
+    "let f = fn() { let g = fn() { y }; g() }; f()",
+    "if (false) { let len = 1; }; len(\"ab\")",
+    "let f = fn() { len }; let len = 5; f()",
+    "let f = fn(x) { push(x, 1) }; let push = fn(x, y) { y }; f([])",
+    // arrays, hashes and builtins
+    "[1, 2 * 2, 3 + 3][1]",
+    "[1, 2, 3][-1]",
+    "let a = [1, 2, 3]; push(rest(a), first(a) + last(a))",
+    "len(\"hello\") + len([1, 2]) + len({1: 2})",
+    "rest([])",
+    "{\"a\": 1, true: 2, 3: [4]}",
+    "let h = {\"one\": 1, \"two\": 2}; h[\"one\"] + h[\"two\"]",
+    "{}[1]",
+    "let map = fn(arr, f) { if (len(arr) == 0) { [] } else { push(map(rest(arr), f), f(first(arr))) } }; map([1, 2, 3], fn(x) { x * 2 })",
+    // errors
+    "5 + true; 5",
+    "-true",
+    "true + false",
+    "\"a\" - \"b\"",
+    "1 + foo",
+    "let f = fn(x) { x }; 1 + f(1, 2) + 1",
+    "1 + (2 / (1 - 1))",
+    "1.5 % 0",
+    "9223372036854775807 * 2 - 1",
+    "-(-9223372036854775807 - 1)",
+    "let x = 1; x()",
+    "[1, 2][5]",
+    "[1][true]",
+    "5[0]",
+    "{1: 2}[fn(x) { x }]",
+    "{[1]: 2}",
+    "{1: 2, [1]: undefined}",
+    "len(1)",
+    "len(1, 2)",
+    "if (10 > 1) { if (true) { return true + false; } return 1; }",
+    "let f = fn() { undefined_name }; f()",
+];
+
+fn parse(input: &str) -> crate::ast::Program {
+    Parser::new(Lexer::new(input.into()))
+        .parse_program()
+        .expect("Program could not be parsed correctly")
+}
+
+fn evaluate(input: &str) -> Result<Object, RuntimeError> {
+    evaluator::eval(&parse(input), &mut Environment::new())
+}
+
+/// The bytecode goes through a bytecode file, so that everything the compiler emits also passes
+/// the checks of the loader.
+fn run(input: &str) -> Result<Object, RuntimeError> {
+    let bytecode = compiler::compile(&parse(input), input).expect("Program could not be compiled");
+    let bytecode =
+        Bytecode::from_bytes(&bytecode.to_bytes()).expect("Bytecode could not be loaded");
+    Vm::new(&bytecode).run()
+}
+
+#[test]
+fn test_evaluator_and_vm_agree() {
+    for input in PROGRAMS {
+        // functions are compared by identity, their printed form is enough here
+        let expected = evaluate(input).map(|result| result.to_string());
+        let actual = run(input).map(|result| result.to_string());
+
+        assert_eq!(actual, expected, "input: {}", input);
+    }
+}
+
+#[test]
+fn test_recursion_limit() {
+    let count_down = |n: usize| {
+        format!(
+            "let f = fn(n) {{ if (n == 0) {{ 0 }} else {{ 1 + f(n - 1) }} }}; f({})",
+            n
+        )
+    };
+
+    // deep recursion in the evaluator needs more stack than test threads have in debug builds
+    let thread = std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(move || {
+            let deepest = count_down(MAX_CALL_DEPTH - 1);
+            let expected = Ok(Object::Integer(MAX_CALL_DEPTH as i64 - 1));
+            assert_eq!(evaluate(&deepest), expected);
+            assert_eq!(run(&deepest), expected);
+
+            let too_deep = count_down(MAX_CALL_DEPTH);
+            let error = evaluate(&too_deep).unwrap_err();
+            assert_eq!(error.get_message(), "stack overflow");
+            assert_eq!(run(&too_deep), Err(error));
+        });
+
+    thread.unwrap().join().unwrap();
+}
+
+/// Run with `make bench`.
+#[test]
+#[ignore]
+fn bench_evaluator_and_vm() {
+    let input = "
+        let fibonacci = fn(n) { if (n < 2) { n } else { fibonacci(n - 1) + fibonacci(n - 2) } };
+        fibonacci(25)
+    ";
+
+    let start = std::time::Instant::now();
+    let expected = evaluate(input);
+    println!("evaluator: {:>8.2?}", start.elapsed());
+
+    let start = std::time::Instant::now();
+    let actual = run(input);
+    println!("vm:        {:>8.2?}", start.elapsed());
+
+    assert_eq!(actual, expected);
+}
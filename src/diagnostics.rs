@@ -0,0 +1,209 @@
+use std::io::IsTerminal;
+
+use crate::span::{SourceMap, Span};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Error pointing at a span of the source, rendered with the affected line and a caret underline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    message: String,
+    span: Span,
+    help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, span: Span) -> Diagnostic {
+        Diagnostic {
+            message,
+            span,
+            help: Default::default(),
+        }
+    }
+
+    pub fn with_help(mut self, help: String) -> Diagnostic {
+        self.help.push(help);
+        self
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    pub fn get_help(&self) -> &Vec<String> {
+        &self.help
+    }
+
+    /// Render the diagnostic like this, with ANSI colors if `colored` is set:
+    ///
+    /// ```text
+    /// error: type mismatch: INTEGER + BOOLEAN
+    ///  --> script.monkey:1:9
+    ///   |
+    /// 1 | let x = 5 + true;
+    ///   |         ^^^^^^^^
+    ///   = help: ...
+    /// ```
+    pub fn render(&self, file_name: &str, source: &str, colored: bool) -> String {
+        let paint = |color: &str, text: &str| {
+            if colored {
+                format!("{}{}{}", color, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let location = SourceMap::new(source).get_location(self.span.get_start());
+        let line_number = location.get_line().to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = source.lines().nth(location.get_line() - 1).unwrap_or("");
+
+        let mut output = format!(
+            "{}{}\n",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+        output += &format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            paint(BLUE, "-->"),
+            file_name,
+            location.get_line(),
+            location.get_column()
+        );
+        output += &format!("{} {}\n", gutter, paint(BLUE, "|"));
+        output += &format!(
+            "{} {} {}\n",
+            paint(BLUE, &line_number),
+            paint(BLUE, "|"),
+            line
+        );
+
+        // keep tabs in the padding so that the carets line up with the source line
+        let padding: String = line
+            .chars()
+            .take(location.get_column() - 1)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        // only underline the first line of spans covering multiple lines
+        let remaining = line
+            .chars()
+            .count()
+            .saturating_sub(location.get_column() - 1);
+        let width = source
+            .get(self.span.get_start()..self.span.get_end())
+            .map_or(1, |text| text.chars().count());
+        let carets = width.clamp(1, remaining.max(1));
+
+        output += &format!(
+            "{} {} {}{}\n",
+            gutter,
+            paint(BLUE, "|"),
+            padding,
+            paint(RED, &"^".repeat(carets))
+        );
+
+        for help in &self.help {
+            output += &format!("{} {} {}\n", gutter, paint(CYAN, "= help:"), help);
+        }
+
+        output
+    }
+}
+
+/// Colors are only used when the diagnostics are written to a terminal and `NO_COLOR` is not set.
+pub fn colors_enabled() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Print the diagnostics to stderr.
+pub fn emit(diagnostics: &[Diagnostic], file_name: &str, source: &str) {
+    let colored = colors_enabled();
+    for diagnostic in diagnostics {
+        eprint!("{}", diagnostic.render(file_name, source, colored));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostic;
+    use crate::span::Span;
+
+    #[test]
+    fn test_render() {
+        let source = "let x = 1;\nlet y = x + true;\n";
+        let diagnostic =
+            Diagnostic::new("type mismatch: INTEGER + BOOLEAN".into(), Span::new(19, 27))
+                .with_help("convert one of the operands".into());
+
+        assert_eq!(
+            diagnostic.render("test.monkey", source, false),
+            "error: type mismatch: INTEGER + BOOLEAN
+ --> test.monkey:2:9
+  |
+2 | let y = x + true;
+  |         ^^^^^^^^
+  = help: convert one of the operands
+"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_tabs_and_wide_gutters() {
+        let source = format!("{}\tfoo;\n", "\n".repeat(11));
+        let diagnostic = Diagnostic::new("identifier not found: foo".into(), Span::new(12, 15));
+
+        assert_eq!(
+            diagnostic.render("test.monkey", &source, false),
+            "error: identifier not found: foo
+  --> test.monkey:12:2
+   |
+12 | \tfoo;
+   | \t^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_render_multi_line_and_empty_spans() {
+        let source = "let s = \"abc\nlet";
+        let unterminated = Diagnostic::new("unterminated".into(), Span::new(8, 16));
+        let eof = Diagnostic::new("unexpected end".into(), Span::new(16, 16));
+
+        assert!(unterminated
+            .render("test.monkey", source, false)
+            .ends_with("1 | let s = \"abc\n  |         ^^^^\n"));
+        assert!(eof
+            .render("test.monkey", source, false)
+            .ends_with("2 | let\n  |    ^\n"));
+    }
+
+    #[test]
+    fn test_render_non_ascii() {
+        let source = "let s = \"äö\" + 1;";
+        let diagnostic =
+            Diagnostic::new("type mismatch: STRING + INTEGER".into(), Span::new(8, 18));
+
+        assert!(diagnostic
+            .render("test.monkey", source, false)
+            .ends_with("1 | let s = \"äö\" + 1;\n  |         ^^^^^^^^\n"));
+    }
+
+    #[test]
+    fn test_render_colored() {
+        let diagnostic = Diagnostic::new("oops".into(), Span::new(0, 1));
+        let rendered = diagnostic.render("test.monkey", "x", true);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
@@ -0,0 +1,1125 @@
+pub(crate) mod builtins;
+mod convert;
+mod environment;
+mod object;
+
+pub use convert::{FromArguments, FromValue, IntoValue};
+pub use environment::Environment;
+pub(crate) use object::{Binding, Variable};
+pub use object::{
+    Builtin, BuiltinFunction, Closure, Function, HashKey, HashPair, Object, RuntimeError,
+};
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{BlockStatement, Expression, Program, Statement},
+    span::Span,
+};
+
+/// Function calls nested deeper than this are reported as an error, by the evaluator as well as
+/// the vm. The evaluator recurses on the stack of the host, a call takes about 3 KiB of it in
+/// release builds and 12 KiB in debug builds.
+pub(crate) const MAX_CALL_DEPTH: usize = 512;
+
+/// Why the evaluation stopped before the end of a block, a `return` unwinds to the function that
+/// is called and an error to the program.
+enum Interrupt {
+    Return(Object),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(error: RuntimeError) -> Interrupt {
+        Interrupt::Error(error)
+    }
+}
+
+pub fn eval(program: &Program, env: &mut Environment) -> Result<Object, RuntimeError> {
+    let mut result = Object::Null;
+
+    for statement in program.get_statements() {
+        result = match eval_statement(statement, env) {
+            Ok(value) => value,
+            Err(Interrupt::Return(value)) => return Ok(value),
+            Err(Interrupt::Error(error)) => return Err(error),
+        };
+    }
+
+    Ok(result)
+}
+
+fn eval_block_statement(
+    block: &BlockStatement,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let mut result = Object::Null;
+
+    for statement in block.get_statements() {
+        result = eval_statement(statement, env)?;
+    }
+
+    Ok(result)
+}
+
+fn eval_statement(statement: &Statement, env: &mut Environment) -> Result<Object, Interrupt> {
+    match statement {
+        Statement::Let { name, value, .. } => {
+            let value = eval_expression(value, env)?;
+            env.set(name.get_value().clone(), value);
+            Ok(Object::Null)
+        }
+        Statement::Return { value, .. } => Err(Interrupt::Return(eval_expression(value, env)?)),
+        Statement::Expression { value, .. } => eval_expression(value, env),
+    }
+}
+
+fn eval_expression(expression: &Expression, env: &mut Environment) -> Result<Object, Interrupt> {
+    // the recursive cases are evaluated by functions of their own, which keeps the stack frame of
+    // this function small as it is on the stack for every level of nesting
+    match expression {
+        Expression::Identifier(identifier) => env
+            .get(identifier.get_value())
+            .or_else(|| builtins::get(identifier.get_value()))
+            .ok_or_else(|| {
+                Interrupt::Error(RuntimeError::new(
+                    format!("identifier not found: {}", identifier.get_value()),
+                    identifier.get_span(),
+                ))
+            }),
+        Expression::Integer { value, .. } => Ok(Object::Integer(*value)),
+        Expression::Float { value, .. } => Ok(Object::Float(*value)),
+        Expression::Boolean { value, .. } => Ok(Object::Boolean(*value)),
+        Expression::String { value, .. } => Ok(Object::String(value.clone())),
+        Expression::Prefix {
+            operator, right, ..
+        } => eval_prefix(operator, right, expression.get_span(), env),
+        Expression::Infix {
+            left,
+            operator,
+            right,
+            ..
+        } => eval_infix(left, operator, right, expression.get_span(), env),
+        Expression::Grouped { expression, .. } => eval_expression(expression, env),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => eval_if(condition, consequence, alternative.as_ref(), env),
+        Expression::Function {
+            parameters, body, ..
+        } => Ok(Object::Function(Rc::new(Function::new(
+            parameters.clone(),
+            body.clone(),
+            env.clone(),
+        )))),
+        Expression::Call {
+            function,
+            arguments,
+            ..
+        } => eval_call(function, arguments, expression.get_span(), env),
+        Expression::Array { elements, .. } => eval_expressions(elements, env).map(Object::Array),
+        Expression::Index { left, index, .. } => {
+            eval_index(left, index, expression.get_span(), env)
+        }
+        Expression::Hash { pairs, .. } => eval_hash(pairs, env),
+    }
+}
+
+fn eval_expressions(
+    expressions: &[Expression],
+    env: &mut Environment,
+) -> Result<Vec<Object>, Interrupt> {
+    let mut evaluated = Vec::with_capacity(expressions.len());
+    for expression in expressions {
+        evaluated.push(eval_expression(expression, env)?);
+    }
+
+    Ok(evaluated)
+}
+
+fn eval_prefix(
+    operator: &str,
+    right: &Expression,
+    span: Span,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let right = eval_expression(right, env)?;
+    Ok(eval_prefix_expression(operator, right, span)?)
+}
+
+fn eval_infix(
+    left: &Expression,
+    operator: &str,
+    right: &Expression,
+    span: Span,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let left = eval_expression(left, env)?;
+
+    // the right side is only evaluated if it can still change the result
+    match operator {
+        "&&" if !is_truthy(&left) => Ok(Object::Boolean(false)),
+        "||" if is_truthy(&left) => Ok(Object::Boolean(true)),
+        "&&" | "||" => Ok(Object::Boolean(is_truthy(&eval_expression(right, env)?))),
+        _ => {
+            let right = eval_expression(right, env)?;
+            Ok(eval_infix_expression(operator, left, right, span)?)
+        }
+    }
+}
+
+fn eval_if(
+    condition: &Expression,
+    consequence: &BlockStatement,
+    alternative: Option<&BlockStatement>,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let condition = eval_expression(condition, env)?;
+
+    if is_truthy(&condition) {
+        eval_block_statement(consequence, env)
+    } else if let Some(alternative) = alternative {
+        eval_block_statement(alternative, env)
+    } else {
+        Ok(Object::Null)
+    }
+}
+
+fn eval_call(
+    function: &Expression,
+    arguments: &[Expression],
+    span: Span,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let function = eval_expression(function, env)?;
+    let arguments = eval_expressions(arguments, env)?;
+
+    Ok(apply_function(
+        function,
+        arguments,
+        span,
+        env.get_depth() + 1,
+    )?)
+}
+
+fn eval_index(
+    left: &Expression,
+    index: &Expression,
+    span: Span,
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let left = eval_expression(left, env)?;
+    let index = eval_expression(index, env)?;
+
+    Ok(eval_index_expression(left, index, span)?)
+}
+
+fn eval_hash(
+    pairs: &[(Expression, Expression)],
+    env: &mut Environment,
+) -> Result<Object, Interrupt> {
+    let mut evaluated = HashMap::with_capacity(pairs.len());
+    for (key_expression, value_expression) in pairs {
+        let key = eval_expression(key_expression, env)?;
+        let hash_key = get_hash_key(&key, key_expression.get_span())?;
+        let value = eval_expression(value_expression, env)?;
+
+        evaluated.insert(hash_key, HashPair::new(key, value));
+    }
+
+    Ok(Object::Hash(evaluated))
+}
+
+/// Key of a value in a hash, reported at `span` if the value cannot be hashed.
+pub(crate) fn get_hash_key(key: &Object, span: Span) -> Result<HashKey, RuntimeError> {
+    key.get_hash_key().ok_or_else(|| {
+        RuntimeError::new(
+            format!("unusable as hash key: {}", key.get_type_name()),
+            span,
+        )
+    })
+}
+
+pub(crate) fn eval_index_expression(
+    left: Object,
+    index: Object,
+    span: Span,
+) -> Result<Object, RuntimeError> {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(index)) => {
+            let length = elements.len() as i64;
+            // negative indices count from the end
+            let position = if index < 0 { length + index } else { index };
+
+            if position < 0 || position >= length {
+                return new_error(
+                    span,
+                    format!("index out of bounds: index {}, length {}", index, length),
+                );
+            }
+
+            Ok(elements[position as usize].clone())
+        }
+        (Object::Array(_), index) => new_error(
+            span,
+            format!("index must be an INTEGER, got {}", index.get_type_name()),
+        ),
+        (Object::Hash(pairs), key) => Ok(pairs
+            .get(&get_hash_key(&key, span)?)
+            .map_or(Object::Null, |pair| pair.get_value().clone())),
+        (left, _) => new_error(
+            span,
+            format!("index operator not supported: {}", left.get_type_name()),
+        ),
+    }
+}
+
+/// `depth` is the number of calls on the stack including this one.
+fn apply_function(
+    function: Object,
+    arguments: Vec<Object>,
+    span: Span,
+    depth: usize,
+) -> Result<Object, RuntimeError> {
+    let function = match function {
+        Object::Function(function) => function,
+        Object::Builtin(builtin) => {
+            return builtin
+                .call(&arguments)
+                .map_err(|error| RuntimeError::new(error.get_message().clone(), span))
+        }
+        other => return new_error(span, format!("not a function: {}", other.get_type_name())),
+    };
+
+    if function.get_parameters().len() != arguments.len() {
+        return new_error(
+            span,
+            format!(
+                "wrong number of arguments: want={}, got={}",
+                function.get_parameters().len(),
+                arguments.len()
+            ),
+        );
+    }
+
+    if depth > MAX_CALL_DEPTH {
+        return new_error(span, "stack overflow".into());
+    }
+
+    let mut env = Environment::new_call(function.get_env().clone(), depth);
+    for (parameter, argument) in function.get_parameters().iter().zip(arguments) {
+        env.set(parameter.get_value().clone(), argument);
+    }
+
+    match eval_block_statement(function.get_body(), &mut env) {
+        Ok(value) | Err(Interrupt::Return(value)) => Ok(value),
+        Err(Interrupt::Error(error)) => Err(error),
+    }
+}
+
+/// Everything except `false` and `null` counts as true in conditions.
+pub(crate) fn is_truthy(object: &Object) -> bool {
+    !matches!(object, Object::Boolean(false) | Object::Null)
+}
+
+fn new_error(span: Span, message: String) -> Result<Object, RuntimeError> {
+    Err(RuntimeError::new(message, span))
+}
+
+pub(crate) fn eval_prefix_expression(
+    operator: &str,
+    right: Object,
+    span: Span,
+) -> Result<Object, RuntimeError> {
+    match (operator, right) {
+        ("!", right) => Ok(Object::Boolean(!is_truthy(&right))),
+        ("-", Object::Integer(value)) => match value.checked_neg() {
+            Some(value) => Ok(Object::Integer(value)),
+            None => new_error(span, format!("integer overflow: -({})", value)),
+        },
+        ("-", Object::Float(value)) => Ok(Object::Float(-value)),
+        (operator, right) => new_error(
+            span,
+            format!("unknown operator: {}{}", operator, right.get_type_name()),
+        ),
+    }
+}
+
+pub(crate) fn eval_infix_expression(
+    operator: &str,
+    left: Object,
+    right: Object,
+    span: Span,
+) -> Result<Object, RuntimeError> {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            eval_integer_infix_expression(operator, left, right, span)
+        }
+        (Object::Float(left), Object::Float(right)) => {
+            eval_float_infix_expression(operator, left, right, span)
+        }
+        // integers are promoted when they are mixed with floats
+        (Object::Integer(left), Object::Float(right)) => {
+            eval_float_infix_expression(operator, left as f64, right, span)
+        }
+        (Object::Float(left), Object::Integer(right)) => {
+            eval_float_infix_expression(operator, left, right as f64, span)
+        }
+        (Object::String(left), Object::String(right)) if operator == "+" => {
+            Ok(Object::String(left + &right))
+        }
+        (left, right) if left.get_type_name() != right.get_type_name() => new_error(
+            span,
+            format!(
+                "type mismatch: {} {} {}",
+                left.get_type_name(),
+                operator,
+                right.get_type_name()
+            ),
+        ),
+        (left, right) => match operator {
+            "==" => Ok(Object::Boolean(left == right)),
+            "!=" => Ok(Object::Boolean(left != right)),
+            _ => new_error(
+                span,
+                format!(
+                    "unknown operator: {} {} {}",
+                    left.get_type_name(),
+                    operator,
+                    right.get_type_name()
+                ),
+            ),
+        },
+    }
+}
+
+fn eval_integer_infix_expression(
+    operator: &str,
+    left: i64,
+    right: i64,
+    span: Span,
+) -> Result<Object, RuntimeError> {
+    let checked = |result: Option<i64>| match result {
+        Some(value) => Ok(Object::Integer(value)),
+        None => new_error(
+            span,
+            format!("integer overflow: {} {} {}", left, operator, right),
+        ),
+    };
+
+    match operator {
+        "/" | "%" if right == 0 => new_error(
+            span,
+            format!("division by zero: {} {} {}", left, operator, right),
+        ),
+        "+" => checked(left.checked_add(right)),
+        "-" => checked(left.checked_sub(right)),
+        "*" => checked(left.checked_mul(right)),
+        "/" => checked(left.checked_div(right)),
+        "%" => checked(left.checked_rem(right)),
+        "<" => Ok(Object::Boolean(left < right)),
+        ">" => Ok(Object::Boolean(left > right)),
+        "<=" => Ok(Object::Boolean(left <= right)),
+        ">=" => Ok(Object::Boolean(left >= right)),
+        "==" => Ok(Object::Boolean(left == right)),
+        "!=" => Ok(Object::Boolean(left != right)),
+        _ => new_error(
+            span,
+            format!("unknown operator: INTEGER {} INTEGER", operator),
+        ),
+    }
+}
+
+fn eval_float_infix_expression(
+    operator: &str,
+    left: f64,
+    right: f64,
+    span: Span,
+) -> Result<Object, RuntimeError> {
+    match operator {
+        "/" | "%" if right == 0.0 => new_error(
+            span,
+            format!("division by zero: {:?} {} {:?}", left, operator, right),
+        ),
+        "+" => Ok(Object::Float(left + right)),
+        "-" => Ok(Object::Float(left - right)),
+        "*" => Ok(Object::Float(left * right)),
+        "/" => Ok(Object::Float(left / right)),
+        "%" => Ok(Object::Float(left % right)),
+        "<" => Ok(Object::Boolean(left < right)),
+        ">" => Ok(Object::Boolean(left > right)),
+        "<=" => Ok(Object::Boolean(left <= right)),
+        ">=" => Ok(Object::Boolean(left >= right)),
+        "==" => Ok(Object::Boolean(left == right)),
+        "!=" => Ok(Object::Boolean(left != right)),
+        _ => new_error(span, format!("unknown operator: FLOAT {} FLOAT", operator)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{eval, Environment, HashKey, Object, RuntimeError};
+    use crate::{diagnostics::Diagnostic, lexer::Lexer, parser::Parser, span::Span};
+
+    fn eval_result(input: &str) -> Result<Object, RuntimeError> {
+        let lexer = Lexer::new(input.into());
+        let parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        if let Err(errors) = program {
+            println!("{}", errors);
+            panic!("Program could not be parsed correctly");
+        }
+
+        eval(&program.unwrap(), &mut Environment::new())
+    }
+
+    fn eval_input(input: &str) -> Object {
+        eval_result(input).unwrap_or_else(|error| panic!("{} failed: {}", input, error))
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = [
+            ("0", 0),
+            ("5", 5),
+            ("10", 10),
+            ("-5", -5),
+            ("-10", -10),
+            ("0x10 + 0b11 + 0o7", 26),
+            ("7 / 2", 3),
+            ("-7 / 2", -3),
+            ("7 % 3", 1),
+            ("-7 % 3", -1),
+            ("2 + 7 % 4 * 2", 8),
+            ("9223372036854775807 - 1 + 1", 9223372036854775807),
+            ("1_000 * 2", 2000),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * 2 * 2 * 2 * 2", 32),
+            ("-50 + 100 + -50", 0),
+            ("5 * 2 + 10", 20),
+            ("5 + 2 * 10", 25),
+            ("20 + 2 * -10", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("2 * (5 + 10)", 30),
+            ("3 * 3 * 3 + 10", 37),
+            ("3 * (3 * 3) + 10", 37),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_float_expression() {
+        let tests = [
+            ("3.5", 3.5),
+            ("-2.5", -2.5),
+            ("1.5 + 1.5", 3.0),
+            ("2.5 * 2.5", 6.25),
+            ("10.5 / 2.1 - 1.5", 3.5),
+            ("1 + 0.5", 1.5),
+            ("0.5 + 1", 1.5),
+            ("3 / 2.0", 1.5),
+            ("2 * 1.25 - 1", 1.5),
+            ("5.5 % 2", 1.5),
+            ("-3.5 % 2", -1.5),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Float(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = [
+            ("true", true),
+            ("false", false),
+            ("1 < 2", true),
+            ("1 > 2", false),
+            ("1 == 1", true),
+            ("1 != 1", false),
+            ("1.5 < 2.5", true),
+            ("true == true", true),
+            ("true != false", true),
+            ("(1 < 2) == true", true),
+            ("(1 > 2) == true", false),
+            ("1 <= 1", true),
+            ("2 <= 1", false),
+            ("1 >= 1", true),
+            ("1 >= 2", false),
+            ("1 < 1.5", true),
+            ("2.0 == 2", true),
+            ("1 != 1.0", false),
+            ("2 >= 2.5", false),
+            ("1.5 <= 1.5", true),
+            ("2.5 >= 3.5", false),
+            ("true && true", true),
+            ("true && false", false),
+            ("false || true", true),
+            ("false || false", false),
+            ("1 < 2 && 2 < 3", true),
+            ("1 > 2 || 2 > 3", false),
+            ("1 && \"a\"", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Boolean(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_short_circuit_evaluation() {
+        let tests = [
+            ("false && undefined", false),
+            ("true || undefined", true),
+            ("let x = [1]; len(x) > 1 && x[1] > 5", false),
+            ("let f = fn() { 1 / (1 - 1) }; true || f()", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Boolean(expected),
+                "input: {}",
+                input
+            );
+        }
+
+        let Err(error) = eval_result("true && undefined") else {
+            panic!("Expected the right side to be evaluated");
+        };
+        assert_eq!(error.get_message(), "identifier not found: undefined");
+    }
+
+    #[test]
+    fn test_string_expression() {
+        let tests = [
+            ("\"Hello World!\"", "Hello World!"),
+            ("\"Hello\" + \" \" + \"World!\"", "Hello World!"),
+            ("\"tab\\tand \\u{1F600}\"", "tab\tand \u{1F600}"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::String(expected.into()),
+                "input: {}",
+                input
+            );
+        }
+
+        assert_eq!(eval_input("\"a\" == \"a\""), Object::Boolean(true));
+        assert_eq!(eval_input("\"a\" != \"a\""), Object::Boolean(false));
+    }
+
+    #[test]
+    fn test_bang_operator() {
+        let tests = [
+            ("!true", false),
+            ("!false", true),
+            ("!5", false),
+            ("!!true", true),
+            ("!!false", false),
+            ("!!5", true),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Boolean(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions() {
+        let tests = [
+            ("if (true) { 10 }", Object::Integer(10)),
+            ("if (false) { 10 }", Object::Null),
+            ("if (1) { 10 }", Object::Integer(10)),
+            ("if (1 < 2) { 10 }", Object::Integer(10)),
+            ("if (1 > 2) { 10 }", Object::Null),
+            ("if (1 > 2) { 10 } else { 20 }", Object::Integer(20)),
+            ("if (1 < 2) { 10 } else { 20 }", Object::Integer(10)),
+            ("if (\"\") { 10 } else { 20 }", Object::Integer(10)),
+            (
+                "let x = 3; if (x > 1) { x * 2 } else { x }",
+                Object::Integer(6),
+            ),
+            (
+                "if (if (false) { 1 }) { 10 } else { 20 }",
+                Object::Integer(20),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_input(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let tests = [
+            ("return 10;", 10),
+            ("return 10; 9;", 10),
+            ("return 2 * 5; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", 10),
+            (
+                "let f = fn(x) { if (x > 1) { return x; } 1 }; f(10) + 1;",
+                11,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = [
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; b;", 5),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_environment_persists_between_programs() {
+        let mut env = Environment::new();
+
+        for (input, expected) in [("let a = 5;", Object::Null), ("a * 2", Object::Integer(10))] {
+            let program = Parser::new(Lexer::new(input.into()))
+                .parse_program()
+                .unwrap();
+            assert_eq!(eval(&program, &mut env), Ok(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_object() {
+        match eval_input("fn(x) { x + 2; };") {
+            Object::Function(function) => {
+                let parameters: Vec<&String> = function
+                    .get_parameters()
+                    .iter()
+                    .map(|p| p.get_value())
+                    .collect();
+
+                assert_eq!(parameters, vec!["x"]);
+                assert_eq!(function.get_body().get_statements().len(), 1);
+            }
+            object => panic!("Expected function, got {:?}", object),
+        }
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            ("let f = fn() { return 1; 2; }; f() + 1;", 2),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = [
+            (
+                "let new_adder = fn(x) { fn(y) { x + y } }; let add_two = new_adder(2); add_two(3);",
+                5,
+            ),
+            (
+                "let x = 10; let f = fn(x) { x * 2 }; f(1) + x;",
+                12,
+            ),
+            (
+                "let counter = fn(start) { let step = 3; fn() { start + step } }; counter(4)();",
+                7,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_higher_order_functions() {
+        let input = "
+            let apply_twice = fn(f, x) { f(f(x)) };
+            let compose = fn(f, g) { fn(x) { g(f(x)) } };
+            let inc = fn(x) { x + 1 };
+            let double = fn(x) { x * 2 };
+
+            compose(inc, double)(apply_twice(inc, 3));
+        ";
+
+        assert_eq!(eval_input(input), Object::Integer(12));
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let input = "
+            let fibonacci = fn(n) {
+                if (n < 2) { n } else { fibonacci(n - 1) + fibonacci(n - 2) }
+            };
+            let count_down = fn(n) { if (n == 1) { return 1; } count_down(n - 1) };
+
+            fibonacci(15) + count_down(50);
+        ";
+
+        assert_eq!(eval_input(input), Object::Integer(611));
+    }
+
+    #[test]
+    fn test_function_can_reference_itself() {
+        let input = "
+            let f = fn() { f };
+            f() == f;
+        ";
+
+        assert_eq!(eval_input(input), Object::Boolean(true));
+    }
+
+    #[test]
+    fn test_array_literals() {
+        assert_eq!(
+            eval_input("[1, 2 * 2, 3 + 3, \"a\"]"),
+            Object::Array(vec![
+                Object::Integer(1),
+                Object::Integer(4),
+                Object::Integer(6),
+                Object::String("a".into()),
+            ])
+        );
+        assert_eq!(eval_input("[]"), Object::Array(vec![]));
+        assert_eq!(eval_input("[1, [2]]").to_string(), "[1, [2]]");
+    }
+
+    #[test]
+    fn test_index_expressions() {
+        let tests = [
+            ("[1, 2, 3][1 - 1]", 1),
+            ("[1, 2, 3][1]", 2),
+            ("[1, 2, 3][2]", 3),
+            ("let i = 1 - 1; [1][i];", 1),
+            ("[1, 2, 3][1 + 1];", 3),
+            ("let my_array = [1, 2, 3]; my_array[2];", 3),
+            (
+                "let my_array = [1, 2, 3]; my_array[-3] + my_array[1] + my_array[2];",
+                6,
+            ),
+            ("[1, 2, 3][-1]", 3),
+            ("[1, 2, 3][-3]", 1),
+            ("[[1, 2], [3, 4]][1][-2]", 3),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input),
+                Object::Integer(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_index_with_zero_literal() {
+        let tests = [
+            ("[1, 2, 3][0]", Object::Integer(1)),
+            ("let i = 0; [1][i];", Object::Integer(1)),
+            ("[[0, 1]][0][0_0]", Object::Integer(0)),
+            ("len([]) == 0", Object::Boolean(true)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_input(input), expected, "input: {}", input);
+        }
+
+        match eval_result("[][0]") {
+            Err(error) => assert_eq!(
+                error.get_message(),
+                "index out of bounds: index 0, length 0"
+            ),
+            Ok(object) => panic!("Expected error, got {:?}", object),
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let tests = [
+            ("len(\"\")", Object::Integer(0)),
+            ("len(\"four\")", Object::Integer(4)),
+            ("len(\"äöü\")", Object::Integer(3)),
+            ("len([1, 2, 3])", Object::Integer(3)),
+            ("first([1, 2, 3])", Object::Integer(1)),
+            ("first([])", Object::Null),
+            ("last([1, 2, 3])", Object::Integer(3)),
+            ("last([])", Object::Null),
+            (
+                "rest([1, 2, 3])",
+                Object::Array(vec![Object::Integer(2), Object::Integer(3)]),
+            ),
+            ("rest([])", Object::Null),
+            ("push([], 1)", Object::Array(vec![Object::Integer(1)])),
+            (
+                "let a = [1]; push(a, 2); a",
+                Object::Array(vec![Object::Integer(1)]),
+            ),
+            ("let len = fn(x) { 42 }; len([1])", Object::Integer(42)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_input(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_list_processing() {
+        let input = "
+            let map = fn(arr, f) {
+                let iter = fn(arr, accumulated) {
+                    if (len(arr) < 1) {
+                        accumulated
+                    } else {
+                        iter(rest(arr), push(accumulated, f(first(arr))))
+                    }
+                };
+
+                iter(arr, [])
+            };
+            let reduce = fn(arr, initial, f) {
+                if (len(arr) < 1) { initial } else { reduce(rest(arr), f(initial, first(arr)), f) }
+            };
+
+            let doubled = map([1, 2, 3, 4], fn(x) { x * 2 });
+            reduce(doubled, 1, fn(sum, x) { sum + x }) + last(doubled);
+        ";
+
+        assert_eq!(eval_input(input), Object::Integer(29));
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let input = "
+            let two = \"two\";
+            {
+                \"one\": 10 - 9,
+                two: 1 + 1,
+                \"thr\" + \"ee\": 6 / 2,
+                4: 4,
+                true: 5,
+                false: 6
+            }
+        ";
+
+        let Object::Hash(pairs) = eval_input(input) else {
+            panic!("Expected a hash");
+        };
+
+        let expected = [
+            (HashKey::String("one".into()), 1),
+            (HashKey::String("two".into()), 2),
+            (HashKey::String("three".into()), 3),
+            (HashKey::Integer(4), 4),
+            (HashKey::Boolean(true), 5),
+            (HashKey::Boolean(false), 6),
+        ];
+
+        assert_eq!(pairs.len(), expected.len());
+        for (key, value) in expected {
+            assert_eq!(pairs[&key].get_value(), &Object::Integer(value));
+        }
+
+        assert_eq!(
+            eval_input("{\"b\": 2, \"a\": [1], 1: {true: 1}}").to_string(),
+            "{1: {true: 1}, \"a\": [1], \"b\": 2}"
+        );
+    }
+
+    #[test]
+    fn test_hash_index_expressions() {
+        let tests = [
+            ("{\"foo\": 5}[\"foo\"]", Object::Integer(5)),
+            ("{\"foo\": 5}[\"bar\"]", Object::Null),
+            ("let key = \"foo\"; {\"foo\": 5}[key]", Object::Integer(5)),
+            ("{}[\"foo\"]", Object::Null),
+            ("{5: 5}[5]", Object::Integer(5)),
+            ("{true: 5}[true]", Object::Integer(5)),
+            ("{false: 5}[false]", Object::Integer(5)),
+            ("{1: 1, 1: 2}[1]", Object::Integer(2)),
+            ("len({1: 1, 2: 2})", Object::Integer(2)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_input(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = [
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
+            ("1.5 + true", "type mismatch: FLOAT + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
+            (
+                "if (10 > 1) { true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("if (x) { 1 }", "identifier not found: x"),
+            (
+                "return true + false; 5",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "identifier not found: foobar"),
+            ("\"a\" - \"b\"", "unknown operator: STRING - STRING"),
+            ("10 / (5 - 5)", "division by zero: 10 / 0"),
+            ("10 % 0", "division by zero: 10 % 0"),
+            ("1.5 / 0", "division by zero: 1.5 / 0.0"),
+            ("1 % 0.0", "division by zero: 1.0 % 0.0"),
+            (
+                "9223372036854775807 + 1",
+                "integer overflow: 9223372036854775807 + 1",
+            ),
+            (
+                "-9223372036854775807 - 2",
+                "integer overflow: -9223372036854775807 - 2",
+            ),
+            (
+                "4611686018427387904 * 2",
+                "integer overflow: 4611686018427387904 * 2",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                "integer overflow: -9223372036854775808 / -1",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                "integer overflow: -(-9223372036854775808)",
+            ),
+            ("5()", "not a function: INTEGER"),
+            (
+                "fn(x) { x }(1, 2)",
+                "wrong number of arguments: want=1, got=2",
+            ),
+            ("let f = fn() { y }; f()", "identifier not found: y"),
+            ("[1, 2, 3][3]", "index out of bounds: index 3, length 3"),
+            ("[1, 2, 3][-4]", "index out of bounds: index -4, length 3"),
+            ("[][1 - 1]", "index out of bounds: index 0, length 0"),
+            ("[1][true]", "index must be an INTEGER, got BOOLEAN"),
+            ("1[1]", "index operator not supported: INTEGER"),
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            (
+                "len(\"one\", \"two\")",
+                "wrong number of arguments to `len`: want=1, got=2",
+            ),
+            ("first(1)", "argument to `first` not supported, got INTEGER"),
+            (
+                "push(1, 1)",
+                "argument to `push` not supported, got INTEGER",
+            ),
+            (
+                "{\"name\": 1}[fn(x) { x }]",
+                "unusable as hash key: FUNCTION",
+            ),
+            ("{[1]: 1}", "unusable as hash key: ARRAY"),
+            ("{\"a\": 1}[{}]", "unusable as hash key: HASH"),
+        ];
+
+        for (input, expected) in tests {
+            match eval_result(input) {
+                Err(error) => {
+                    assert_eq!(error.get_message(), expected, "input: {}", input)
+                }
+                Ok(object) => panic!("Expected error for {}, got {:?}", input, object),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let tests = [
+            ("let x = 5 + true;", Span::new(8, 16)),
+            ("-true", Span::new(0, 5)),
+            ("1 + foo", Span::new(4, 7)),
+            ("let f = fn(x) { x }; 1 + f(1, 2) + 1", Span::new(25, 32)),
+            ("1 + (2 / (1 - 1))", Span::new(5, 16)),
+            ("1 + 2 % 0 * 3", Span::new(4, 9)),
+            ("9223372036854775807 * 2 - 1", Span::new(0, 23)),
+        ];
+
+        for (input, expected) in tests {
+            match eval_result(input) {
+                Err(error) => assert_eq!(
+                    error.to_diagnostic(),
+                    Diagnostic::new(error.get_message().clone(), expected),
+                    "input: {}",
+                    input
+                ),
+                Ok(object) => panic!("Expected error for {}, got {:?}", input, object),
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::object::{Builtin, Object, RuntimeError};
+
+/// Functions that are available everywhere, unless a binding with the same name shadows them.
+pub fn get(name: &str) -> Option<Object> {
+    let builtin = match name {
+        "len" => Builtin::new("len", len),
+        "first" => Builtin::new("first", first),
+        "last" => Builtin::new("last", last),
+        "rest" => Builtin::new("rest", rest),
+        "push" => Builtin::new("push", push),
+        _ => return None,
+    };
+
+    Some(Object::Builtin(builtin))
+}
+
+fn check_arity(name: &str, arguments: &[Object], want: usize) -> Result<(), RuntimeError> {
+    if arguments.len() == want {
+        Ok(())
+    } else {
+        Err(format!(
+            "wrong number of arguments to `{}`: want={}, got={}",
+            name,
+            want,
+            arguments.len()
+        )
+        .into())
+    }
+}
+
+fn unsupported(name: &str, argument: &Object) -> RuntimeError {
+    format!(
+        "argument to `{}` not supported, got {}",
+        name,
+        argument.get_type_name()
+    )
+    .into()
+}
+
+fn len(arguments: &[Object]) -> Result<Object, RuntimeError> {
+    check_arity("len", arguments, 1)?;
+
+    match &arguments[0] {
+        Object::String(value) => Ok(Object::Integer(value.chars().count() as i64)),
+        Object::Array(elements) => Ok(Object::Integer(elements.len() as i64)),
+        Object::Hash(pairs) => Ok(Object::Integer(pairs.len() as i64)),
+        other => Err(unsupported("len", other)),
+    }
+}
+
+fn first(arguments: &[Object]) -> Result<Object, RuntimeError> {
+    check_arity("first", arguments, 1)?;
+
+    match &arguments[0] {
+        Object::Array(elements) => Ok(elements.first().cloned().unwrap_or(Object::Null)),
+        other => Err(unsupported("first", other)),
+    }
+}
+
+fn last(arguments: &[Object]) -> Result<Object, RuntimeError> {
+    check_arity("last", arguments, 1)?;
+
+    match &arguments[0] {
+        Object::Array(elements) => Ok(elements.last().cloned().unwrap_or(Object::Null)),
+        other => Err(unsupported("last", other)),
+    }
+}
+
+fn rest(arguments: &[Object]) -> Result<Object, RuntimeError> {
+    check_arity("rest", arguments, 1)?;
+
+    match &arguments[0] {
+        Object::Array(elements) if elements.is_empty() => Ok(Object::Null),
+        Object::Array(elements) => Ok(Object::Array(elements[1..].to_vec())),
+        other => Err(unsupported("rest", other)),
+    }
+}
+
+fn push(arguments: &[Object]) -> Result<Object, RuntimeError> {
+    check_arity("push", arguments, 2)?;
+
+    match &arguments[0] {
+        Object::Array(elements) => {
+            let mut elements = elements.clone();
+            elements.push(arguments[1].clone());
+            Ok(Object::Array(elements))
+        }
+        other => Err(unsupported("push", other)),
+    }
+}
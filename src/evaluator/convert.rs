@@ -0,0 +1,307 @@
+use std::{collections::HashMap, hash::Hash};
+
+use super::object::{HashKey, HashPair, Object, RuntimeError};
+
+/// Conversion of script values into rust types, used for the arguments of host functions.
+pub trait FromValue: Sized {
+    fn from_value(value: &Object) -> Result<Self, RuntimeError>;
+}
+
+/// Conversion of rust types into script values, used for the results of host functions.
+pub trait IntoValue {
+    fn into_value(self) -> Object;
+}
+
+/// Conversion of all arguments of a host function at once, e.g. into `(i64, String)`, which
+/// fails if the number of arguments does not match.
+pub trait FromArguments: Sized {
+    fn from_arguments(arguments: &[Object]) -> Result<Self, RuntimeError>;
+}
+
+fn expected(type_name: &str, value: &Object) -> RuntimeError {
+    format!("expected {}, got {}", type_name, value.get_type_name()).into()
+}
+
+impl FromValue for Object {
+    fn from_value(value: &Object) -> Result<Object, RuntimeError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Object) -> Result<i64, RuntimeError> {
+        match value {
+            Object::Integer(value) => Ok(*value),
+            value => Err(expected("INTEGER", value)),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Object) -> Result<f64, RuntimeError> {
+        match value {
+            Object::Float(value) => Ok(*value),
+            // same promotion as in arithmetic
+            Object::Integer(value) => Ok(*value as f64),
+            value => Err(expected("FLOAT", value)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Object) -> Result<bool, RuntimeError> {
+        match value {
+            Object::Boolean(value) => Ok(*value),
+            value => Err(expected("BOOLEAN", value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Object) -> Result<String, RuntimeError> {
+        match value {
+            Object::String(value) => Ok(value.clone()),
+            value => Err(expected("STRING", value)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Object) -> Result<Vec<T>, RuntimeError> {
+        match value {
+            Object::Array(elements) => elements.iter().map(T::from_value).collect(),
+            value => Err(expected("ARRAY", value)),
+        }
+    }
+}
+
+impl<K, V> FromValue for HashMap<K, V>
+where
+    K: FromValue + Eq + Hash,
+    V: FromValue,
+{
+    fn from_value(value: &Object) -> Result<HashMap<K, V>, RuntimeError> {
+        match value {
+            Object::Hash(pairs) => pairs
+                .values()
+                .map(|pair| {
+                    Ok((
+                        K::from_value(pair.get_key())?,
+                        V::from_value(pair.get_value())?,
+                    ))
+                })
+                .collect(),
+            value => Err(expected("HASH", value)),
+        }
+    }
+}
+
+impl IntoValue for Object {
+    fn into_value(self) -> Object {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Object {
+        Object::Null
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Object {
+        Object::Integer(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Object {
+        Object::Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Object {
+        Object::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Object {
+        self.map_or(Object::Null, T::into_value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Object {
+        Object::Array(self.into_iter().map(T::into_value).collect())
+    }
+}
+
+impl<K, V> IntoValue for HashMap<K, V>
+where
+    K: Into<HashKey>,
+    V: IntoValue,
+{
+    fn into_value(self) -> Object {
+        let pairs = self
+            .into_iter()
+            .map(|(key, value)| {
+                let key: HashKey = key.into();
+                let pair = HashPair::new(key.to_object(), value.into_value());
+                (key, pair)
+            })
+            .collect();
+
+        Object::Hash(pairs)
+    }
+}
+
+impl From<i64> for HashKey {
+    fn from(value: i64) -> HashKey {
+        HashKey::Integer(value)
+    }
+}
+
+impl From<bool> for HashKey {
+    fn from(value: bool) -> HashKey {
+        HashKey::Boolean(value)
+    }
+}
+
+impl From<String> for HashKey {
+    fn from(value: String) -> HashKey {
+        HashKey::String(value)
+    }
+}
+
+impl From<&str> for HashKey {
+    fn from(value: &str) -> HashKey {
+        HashKey::String(value.into())
+    }
+}
+
+fn check_arity(arguments: &[Object], want: usize) -> Result<(), RuntimeError> {
+    if arguments.len() == want {
+        Ok(())
+    } else {
+        Err(format!(
+            "wrong number of arguments: want={}, got={}",
+            want,
+            arguments.len()
+        )
+        .into())
+    }
+}
+
+fn argument<T: FromValue>(arguments: &[Object], index: usize) -> Result<T, RuntimeError> {
+    T::from_value(&arguments[index])
+        .map_err(|e| format!("argument {}: {}", index + 1, e.get_message()).into())
+}
+
+impl FromArguments for () {
+    fn from_arguments(arguments: &[Object]) -> Result<(), RuntimeError> {
+        check_arity(arguments, 0)
+    }
+}
+
+macro_rules! impl_from_arguments {
+    ($count:literal; $($type:ident: $index:tt),+) => {
+        impl<$($type: FromValue),+> FromArguments for ($($type,)+) {
+            fn from_arguments(arguments: &[Object]) -> Result<($($type,)+), RuntimeError> {
+                check_arity(arguments, $count)?;
+                Ok(($(argument::<$type>(arguments, $index)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_arguments!(1; A: 0);
+impl_from_arguments!(2; A: 0, B: 1);
+impl_from_arguments!(3; A: 0, B: 1, C: 2);
+impl_from_arguments!(4; A: 0, B: 1, C: 2, D: 3);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{FromArguments, FromValue, IntoValue};
+    use crate::evaluator::Object;
+
+    #[test]
+    fn test_from_value() {
+        assert_eq!(i64::from_value(&Object::Integer(1)), Ok(1));
+        assert_eq!(f64::from_value(&Object::Integer(1)), Ok(1.0));
+        assert_eq!(bool::from_value(&Object::Boolean(true)), Ok(true));
+        assert_eq!(
+            String::from_value(&Object::String("a".into())),
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            Vec::<i64>::from_value(&Object::Array(vec![Object::Integer(1), Object::Integer(2)])),
+            Ok(vec![1, 2])
+        );
+
+        let error = i64::from_value(&Object::String("1".into())).unwrap_err();
+        assert_eq!(error.get_message(), "expected INTEGER, got STRING");
+
+        let error = Vec::<bool>::from_value(&Object::Array(vec![Object::Null])).unwrap_err();
+        assert_eq!(error.get_message(), "expected BOOLEAN, got NULL");
+    }
+
+    #[test]
+    fn test_hash_round_trip() {
+        let map = HashMap::from([("one".to_string(), 1), ("two".to_string(), 2)]);
+        let value = map.clone().into_value();
+
+        assert_eq!(value.to_string(), "{\"one\": 1, \"two\": 2}");
+        assert_eq!(HashMap::<String, i64>::from_value(&value), Ok(map));
+    }
+
+    #[test]
+    fn test_into_value() {
+        assert_eq!(().into_value(), Object::Null);
+        assert_eq!(Some(1.5).into_value(), Object::Float(1.5));
+        assert_eq!(None::<i64>.into_value(), Object::Null);
+        assert_eq!(
+            vec!["a", "b"].into_value(),
+            Object::Array(vec![Object::String("a".into()), Object::String("b".into())])
+        );
+    }
+
+    #[test]
+    fn test_from_arguments() {
+        let arguments = [Object::Integer(1), Object::String("a".into())];
+
+        assert_eq!(
+            <(i64, String)>::from_arguments(&arguments),
+            Ok((1, "a".to_string()))
+        );
+
+        let error = <(i64,)>::from_arguments(&arguments).unwrap_err();
+        assert_eq!(
+            error.get_message(),
+            "wrong number of arguments: want=1, got=2"
+        );
+
+        let error = <(i64, i64)>::from_arguments(&arguments).unwrap_err();
+        assert_eq!(
+            error.get_message(),
+            "argument 2: expected INTEGER, got STRING"
+        );
+
+        assert!(<()>::from_arguments(&[]).is_ok());
+    }
+}
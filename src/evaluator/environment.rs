@@ -0,0 +1,232 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::{Rc, Weak},
+};
+
+use super::object::{Function, Object};
+
+type Store = RefCell<HashMap<String, Object>>;
+
+/// Bindings of one scope, cheap to clone so that several owners can share (and mutate) the same
+/// scope. Lookups fall back to the enclosing scope when a name is not bound locally.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    store: Rc<Store>,
+    outer: Option<Box<Environment>>,
+    /// Number of function calls on the stack while this scope is evaluated.
+    depth: usize,
+    heap: Rc<Heap>,
+}
+
+/// All scopes created below the same global scope. A function that is stored in a scope it
+/// captured keeps that scope alive through a reference cycle, so the scopes that are only
+/// reachable from each other are cleared from time to time.
+#[derive(Debug, Default)]
+pub(crate) struct Heap {
+    stores: RefCell<Vec<Weak<Store>>>,
+    /// Number of scopes that were alive after the last collection.
+    live: Cell<usize>,
+}
+
+/// Values that are shared by reference and can therefore be part of a cycle.
+enum Node {
+    Store(Rc<Store>),
+    Function(Rc<Function>),
+}
+
+type Key = *const ();
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::with_heap(Rc::default(), None, 0)
+    }
+
+    pub fn new_enclosed(outer: Environment) -> Environment {
+        Environment::with_heap(Rc::clone(&outer.heap), Some(outer), 0)
+    }
+
+    /// Scope of a function call, `depth` counts the calls on the stack including this one.
+    pub(crate) fn new_call(outer: Environment, depth: usize) -> Environment {
+        Environment::with_heap(Rc::clone(&outer.heap), Some(outer), depth)
+    }
+
+    fn with_heap(heap: Rc<Heap>, outer: Option<Environment>, depth: usize) -> Environment {
+        let store = Rc::default();
+        heap.add(&store);
+
+        Environment {
+            store,
+            outer: outer.map(Box::new),
+            depth,
+            heap,
+        }
+    }
+
+    pub(crate) fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub(crate) fn get_heap(&self) -> &Rc<Heap> {
+        &self.heap
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref().and_then(|outer| outer.get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: String, value: Object) {
+        self.store.borrow_mut().insert(name, value);
+    }
+
+    /// The store of this scope followed by the stores of the enclosing scopes.
+    fn stores(&self) -> impl Iterator<Item = &Rc<Store>> {
+        std::iter::successors(Some(self), |env| env.outer.as_deref()).map(|env| &env.store)
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
+impl Heap {
+    // collecting whenever the number of scopes doubled keeps the cost proportional to the number
+    // of scopes that are created
+    fn add(&self, store: &Rc<Store>) {
+        let count = self.stores.borrow().len();
+        if count >= 2 * self.live.get().max(64) {
+            self.collect();
+        }
+
+        self.stores.borrow_mut().push(Rc::downgrade(store));
+    }
+
+    /// Clear the scopes that are only kept alive by each other and return the number of scopes
+    /// that are left. Any other reference to a scope or to a function, e.g. from the host or from
+    /// a call that is still running, keeps it and everything it refers to alive.
+    pub(crate) fn collect(&self) -> usize {
+        let mut nodes: HashMap<Key, (Node, usize)> = HashMap::new();
+        for store in self.stores.borrow().iter().filter_map(Weak::upgrade) {
+            // the upgraded reference is not counted
+            let count = Rc::strong_count(&store) - 1;
+            nodes.insert(key(&store), (Node::Store(store), count));
+        }
+
+        let stores: Vec<Rc<Store>> = nodes
+            .values()
+            .filter_map(|(node, _)| match node {
+                Node::Store(store) => Some(Rc::clone(store)),
+                Node::Function(_) => None,
+            })
+            .collect();
+        for store in &stores {
+            for value in store.borrow().values() {
+                for_each_function(value, &mut |function| {
+                    nodes.entry(key(function)).or_insert_with(|| {
+                        let count = Rc::strong_count(function);
+                        (Node::Function(Rc::clone(function)), count)
+                    });
+                });
+            }
+        }
+
+        // after subtracting the references between the nodes, only the ones from outside are left
+        let references: HashMap<Key, Vec<Key>> = nodes
+            .iter()
+            .map(|(key, (node, _))| (*key, node.get_references()))
+            .collect();
+        for target in references.values().flatten() {
+            if let Some((_, count)) = nodes.get_mut(target) {
+                *count -= 1;
+            }
+        }
+
+        let mut pending: Vec<Key> = nodes
+            .iter()
+            .filter(|(_, (_, count))| *count > 0)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut reachable: HashSet<Key> = pending.iter().copied().collect();
+        while let Some(key) = pending.pop() {
+            for target in &references[&key] {
+                if nodes.contains_key(target) && reachable.insert(*target) {
+                    pending.push(*target);
+                }
+            }
+        }
+
+        let garbage: Vec<HashMap<String, Object>> = nodes
+            .iter()
+            .filter(|(key, _)| !reachable.contains(key))
+            .filter_map(|(_, (node, _))| match node {
+                Node::Store(store) => Some(std::mem::take(&mut *store.borrow_mut())),
+                Node::Function(_) => None,
+            })
+            .collect();
+        // the values are dropped once no store is borrowed anymore
+        drop(garbage);
+        drop(stores);
+        drop(nodes);
+
+        let mut stores = self.stores.borrow_mut();
+        stores.retain(|store| store.strong_count() > 0);
+        self.live.set(stores.len());
+        stores.len()
+    }
+
+    /// Heaps of the functions in these scopes that were created below another global scope, a
+    /// cycle in another heap can be kept alive from here.
+    pub(crate) fn get_other_heaps(self: &Rc<Heap>) -> Vec<Rc<Heap>> {
+        let mut heaps: Vec<Rc<Heap>> = Vec::new();
+        for store in self.stores.borrow().iter().filter_map(Weak::upgrade) {
+            for value in store.borrow().values() {
+                for_each_function(value, &mut |function| {
+                    let heap = function.get_env().get_heap();
+                    if !Rc::ptr_eq(heap, self) && !heaps.iter().any(|h| Rc::ptr_eq(heap, h)) {
+                        heaps.push(Rc::clone(heap));
+                    }
+                });
+            }
+        }
+
+        heaps
+    }
+}
+
+impl Node {
+    /// Scopes and functions this node holds a reference to, once for every reference.
+    fn get_references(&self) -> Vec<Key> {
+        let mut references = Vec::new();
+        match self {
+            Node::Store(store) => {
+                for value in store.borrow().values() {
+                    for_each_function(value, &mut |function| references.push(key(function)));
+                }
+            }
+            Node::Function(function) => references.extend(function.get_env().stores().map(key)),
+        }
+
+        references
+    }
+}
+
+fn key<T>(value: &Rc<T>) -> Key {
+    Rc::as_ptr(value) as Key
+}
+
+/// Call `f` for every function in the value, once for every reference to it.
+fn for_each_function(value: &Object, f: &mut impl FnMut(&Rc<Function>)) {
+    match value {
+        Object::Function(function) => f(function),
+        Object::Array(elements) => elements.iter().for_each(|e| for_each_function(e, f)),
+        Object::Hash(pairs) => pairs
+            .values()
+            .for_each(|pair| for_each_function(pair.get_value(), f)),
+        _ => {}
+    }
+}
@@ -0,0 +1,330 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use super::environment::Environment;
+use crate::{
+    ast::{BlockStatement, Identifier},
+    compiler::CompiledFunction,
+    diagnostics::Diagnostic,
+    span::Span,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Null,
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Builtin(Builtin),
+    Array(Vec<Object>),
+    Hash(HashMap<HashKey, HashPair>),
+}
+
+/// Key of a hash, only values with a stable identity can be hashed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+/// The original key is kept next to the value so the hash can be printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashPair {
+    key: Object,
+    value: Object,
+}
+
+pub type BuiltinFunction = fn(&[Object]) -> Result<Object, RuntimeError>;
+
+type HostFunction = dyn Fn(&[Object]) -> Result<Object, RuntimeError>;
+
+/// Function implemented in rust, errors are reported at the call site by the evaluator.
+#[derive(Clone)]
+pub struct Builtin {
+    name: String,
+    function: Rc<HostFunction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    message: String,
+    span: Span,
+}
+
+/// A function literal together with the environment it was defined in, which makes it a closure.
+pub struct Function {
+    parameters: Vec<Identifier>,
+    body: BlockStatement,
+    env: Environment,
+}
+
+/// A compiled function together with the variables it captured, created by the vm.
+pub struct Closure {
+    function: Rc<CompiledFunction>,
+    free: Vec<Variable>,
+}
+
+/// Local of a call in the vm, shared with the closures that capture it so that they see later
+/// bindings just like closures of the evaluator share its environment. Unlike the scopes of the
+/// evaluator, a closure stored in a variable it captured is never freed.
+pub(crate) type Variable = Rc<RefCell<Binding>>;
+
+#[derive(Debug, Clone)]
+pub(crate) enum Binding {
+    Value(Object),
+    /// Not bound yet, reads go to the variable of the same name in the enclosing scope.
+    Outer(Variable),
+    /// Not bound yet, reads go to the global or else to the builtin of the same name.
+    Global(usize),
+}
+
+impl Object {
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::Function(_) | Object::Closure(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+        }
+    }
+
+    pub fn get_hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Float(value) => write!(f, "{:?}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Null => write!(f, "null"),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure),
+            Object::Builtin(builtin) => write!(f, "builtin function {}", builtin.name),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                // the iteration order of the map is random, sort it to keep the output stable
+                let mut pairs: Vec<(&HashKey, &HashPair)> = pairs.iter().collect();
+                pairs.sort_by(|a, b| a.0.cmp(b.0));
+
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(_, pair)| match &pair.key {
+                        Object::String(key) => format!("{:?}: {}", key, pair.value),
+                        key => format!("{}: {}", key, pair.value),
+                    })
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn new(message: String, span: Span) -> RuntimeError {
+        RuntimeError { message, span }
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(self.message.clone(), self.span)
+    }
+}
+
+/// Errors of host functions are created without a span, the evaluator points them at the call.
+impl From<String> for RuntimeError {
+    fn from(message: String) -> RuntimeError {
+        RuntimeError::new(message, Span::default())
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> RuntimeError {
+        RuntimeError::from(message.to_string())
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "error: {} (at {}..{})",
+            self.message,
+            self.span.get_start(),
+            self.span.get_end()
+        )
+    }
+}
+
+impl HashKey {
+    pub fn to_object(&self) -> Object {
+        match self {
+            HashKey::Integer(value) => Object::Integer(*value),
+            HashKey::Boolean(value) => Object::Boolean(*value),
+            HashKey::String(value) => Object::String(value.clone()),
+        }
+    }
+}
+
+impl HashPair {
+    pub fn new(key: Object, value: Object) -> HashPair {
+        HashPair { key, value }
+    }
+
+    pub fn get_key(&self) -> &Object {
+        &self.key
+    }
+
+    pub fn get_value(&self) -> &Object {
+        &self.value
+    }
+}
+
+impl Builtin {
+    pub fn new<F>(name: &str, function: F) -> Builtin
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        Builtin {
+            name: name.into(),
+            function: Rc::new(function),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn call(&self, arguments: &[Object]) -> Result<Object, RuntimeError> {
+        (self.function)(arguments)
+    }
+}
+
+// builtins are identified by their name, comparing function pointers is not reliable
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Builtin) -> bool {
+        self.name == other.name
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builtin").field("name", &self.name).finish()
+    }
+}
+
+impl Function {
+    pub fn new(parameters: Vec<Identifier>, body: BlockStatement, env: Environment) -> Function {
+        Function {
+            parameters,
+            body,
+            env,
+        }
+    }
+
+    pub fn get_parameters(&self) -> &Vec<Identifier> {
+        &self.parameters
+    }
+
+    pub fn get_body(&self) -> &BlockStatement {
+        &self.body
+    }
+
+    pub fn get_env(&self) -> &Environment {
+        &self.env
+    }
+}
+
+// functions are only equal to themselves, comparing the bodies would not take the captured
+// environment into account
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// the captured environment is left out, as it can contain the function itself
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Closure {
+    pub(crate) fn new(function: Rc<CompiledFunction>, free: Vec<Variable>) -> Closure {
+        Closure { function, free }
+    }
+
+    pub fn get_function(&self) -> &Rc<CompiledFunction> {
+        &self.function
+    }
+
+    pub(crate) fn get_free(&self) -> &Vec<Variable> {
+        &self.free
+    }
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("parameters", self.function.get_parameters())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "fn({}) {{ ... }}",
+            self.function.get_parameters().join(", ")
+        )
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters: Vec<&str> = self
+            .parameters
+            .iter()
+            .map(|p| p.get_value().as_str())
+            .collect();
+
+        write!(f, "fn({}) {{ ... }}", parameters.join(", "))
+    }
+}
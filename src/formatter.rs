@@ -0,0 +1,481 @@
+use crate::{
+    ast::{BlockStatement, Expression, Program, Statement},
+    lexer::Lexer,
+    parser::{Parser, ParserErrorList},
+    span::Span,
+    token::{Token, TokenType},
+};
+
+const INDENT: &str = "    ";
+
+/// Prints a program in the canonical style. The comments are not part of the syntax tree, they
+/// are put back in front of the statement that follows them, or behind the statement they are
+/// written in.
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Token>,
+    next_comment: usize,
+    output: String,
+    depth: usize,
+    /// End of the statement or comment printed last in the current block, to keep blank lines.
+    last_end: Option<usize>,
+}
+
+/// Format source code: one statement per line, blocks indented by four spaces, single spaces
+/// around binary operators and a semicolon after each statement. The last expression of a block
+/// is its value and is left without a semicolon. `if` expressions keep theirs as well, otherwise
+/// a following `-x`, `[...]` or `(...)` would continue the `if` as an operand. Literals are kept
+/// as they are written, as well as parentheses and single blank lines between statements.
+pub fn format(source: &str) -> Result<String, ParserErrorList> {
+    let program = Parser::new(Lexer::new(source.into())).parse_program()?;
+
+    let mut lexer = Lexer::new(source.into()).with_comments();
+    let mut comments = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        match token.get_type() {
+            TokenType::Comment => comments.push(token),
+            TokenType::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut formatter = Formatter {
+        source,
+        comments,
+        next_comment: 0,
+        output: String::new(),
+        depth: 0,
+        last_end: None,
+    };
+    formatter.program(&program);
+
+    Ok(formatter.output)
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &Program) {
+        self.statements(program.get_statements(), false);
+        self.leading_comments(usize::MAX);
+    }
+
+    /// Print each statement on its own line, `is_block` leaves the semicolon off the last
+    /// expression as it is the value of the block.
+    fn statements(&mut self, statements: &[Statement], is_block: bool) {
+        for (i, statement) in statements.iter().enumerate() {
+            let span = statement.get_span();
+            self.leading_comments(span.get_start());
+            self.start_line(span.get_start());
+
+            let semicolon = match statement {
+                Statement::Let { name, value, .. } => {
+                    self.output += &format!("let {} = ", name.get_value());
+                    self.expression(value);
+                    true
+                }
+                Statement::Return { value, .. } => {
+                    self.output += "return ";
+                    self.expression(value);
+                    true
+                }
+                Statement::Expression { value, .. } => {
+                    self.expression(value);
+                    !(is_block && i == statements.len() - 1)
+                }
+            };
+            if semicolon {
+                self.output.push(';');
+            }
+
+            let end = self.trailing_comments(span.get_end());
+            self.output.push('\n');
+            self.last_end = Some(end);
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        let end = block.get_span().get_end();
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.get_span().get_start() < end);
+
+        if block.get_statements().is_empty() && !has_comments {
+            self.output += "{}";
+            return;
+        }
+
+        self.output += "{\n";
+        self.depth += 1;
+        let outer_end = self.last_end.take();
+
+        self.statements(block.get_statements(), true);
+        self.leading_comments(end);
+
+        self.last_end = outer_end;
+        self.depth -= 1;
+        self.output += &INDENT.repeat(self.depth);
+        self.output.push('}');
+    }
+
+    /// Indent a new line, keeping one blank line if there is one in the source before `start`.
+    fn start_line(&mut self, start: usize) {
+        if let Some(last_end) = self.last_end {
+            let gap = self.source.get(last_end..start).unwrap_or_default();
+            if gap.matches('\n').count() > 1 {
+                self.output.push('\n');
+            }
+        }
+
+        self.output += &INDENT.repeat(self.depth);
+    }
+
+    /// Print the comments before `offset` on their own lines.
+    fn leading_comments(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            let span = comment.get_span();
+            if span.get_start() >= offset {
+                break;
+            }
+
+            let literal = comment.get_literal();
+            self.start_line(span.get_start());
+            self.output += &literal;
+            self.output.push('\n');
+
+            self.last_end = Some(span.get_end());
+            self.next_comment += 1;
+        }
+    }
+
+    /// Print the comments inside of a statement that ends at `end` and the ones following it on
+    /// the same line behind it, returns the end of the last one.
+    fn trailing_comments(&mut self, mut end: usize) -> usize {
+        let mut first = true;
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            let span = comment.get_span();
+            let on_same_line = self
+                .source
+                .get(end..span.get_start())
+                .is_some_and(|gap| gap.chars().all(|c| matches!(c, ' ' | '\t' | ';')));
+            if span.get_start() >= end && !on_same_line {
+                break;
+            }
+
+            // only the first one fits behind the statement, a line comment ends the line
+            if first {
+                self.output.push(' ');
+                first = false;
+            } else {
+                self.output.push('\n');
+                self.output += &INDENT.repeat(self.depth);
+            }
+            self.output += &comment.get_literal();
+            self.next_comment += 1;
+            end = end.max(span.get_end());
+        }
+
+        end
+    }
+
+    /// Print the comments before `offset` inside of an expression, at their position between the
+    /// sub-expressions. A line comment continues the expression on the next line.
+    fn inline_comments(&mut self, offset: usize) -> bool {
+        let mut printed = false;
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.get_span().get_start() >= offset {
+                break;
+            }
+
+            let literal = comment.get_literal();
+            if !self.output.ends_with([' ', '\n', '(', '[', '{']) {
+                self.output.push(' ');
+            }
+            self.output += &literal;
+            if literal.starts_with("//") {
+                self.output.push('\n');
+                self.output += &INDENT.repeat(self.depth + 1);
+            } else {
+                self.output.push(' ');
+            }
+
+            self.next_comment += 1;
+            printed = true;
+        }
+
+        printed
+    }
+
+    /// Print the comments before the closing delimiter at `offset`, which follows them directly.
+    fn closing_comments(&mut self, offset: usize) {
+        if self.inline_comments(offset) {
+            let len = self.output.trim_end_matches(' ').len();
+            self.output.truncate(len);
+            if self.output.ends_with('\n') {
+                self.output += &INDENT.repeat(self.depth);
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.inline_comments(expression.get_span().get_start());
+
+        match expression {
+            Expression::Identifier(identifier) => self.output += identifier.get_value(),
+            // literals are kept as written, e.g. hexadecimal numbers and escape sequences
+            Expression::Integer { token, .. }
+            | Expression::Float { token, .. }
+            | Expression::Boolean { token, .. }
+            | Expression::String { token, .. } => self.source_text(token.get_span()),
+            Expression::Prefix {
+                operator, right, ..
+            } => {
+                self.output += operator;
+                self.expression(right);
+            }
+            Expression::Infix {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                self.expression(left);
+                self.output += &format!(" {} ", operator);
+                self.expression(right);
+            }
+            Expression::Grouped {
+                expression, span, ..
+            } => {
+                self.output.push('(');
+                self.expression(expression);
+                self.closing_comments(span.get_end() - 1);
+                self.output.push(')');
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                self.output += "if (";
+                self.expression(condition);
+                self.output += ") ";
+                self.block(consequence);
+
+                if let Some(alternative) = alternative {
+                    self.output += " else ";
+                    self.block(alternative);
+                }
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                let parameters: Vec<&str> =
+                    parameters.iter().map(|p| p.get_value().as_str()).collect();
+                self.output += &format!("fn({}) ", parameters.join(", "));
+                self.block(body);
+            }
+            Expression::Call {
+                function,
+                arguments,
+                span,
+                ..
+            } => {
+                self.expression(function);
+                self.output.push('(');
+                self.list(arguments);
+                self.closing_comments(span.get_end() - 1);
+                self.output.push(')');
+            }
+            Expression::Array { elements, span, .. } => {
+                self.output.push('[');
+                self.list(elements);
+                self.closing_comments(span.get_end() - 1);
+                self.output.push(']');
+            }
+            Expression::Index {
+                left, index, span, ..
+            } => {
+                self.expression(left);
+                self.output.push('[');
+                self.expression(index);
+                self.closing_comments(span.get_end() - 1);
+                self.output.push(']');
+            }
+            Expression::Hash { pairs, span, .. } => {
+                self.output.push('{');
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.output += ", ";
+                    }
+                    self.expression(key);
+                    self.output += ": ";
+                    self.expression(value);
+                }
+                self.closing_comments(span.get_end() - 1);
+                self.output.push('}');
+            }
+        }
+    }
+
+    fn list(&mut self, expressions: &[Expression]) {
+        for (i, expression) in expressions.iter().enumerate() {
+            if i > 0 {
+                self.output += ", ";
+            }
+            self.expression(expression);
+        }
+    }
+
+    fn source_text(&mut self, span: Span) {
+        self.output += &self.source[span.get_start()..span.get_end()];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::{
+        evaluator::{self, Environment},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    fn evaluate(input: &str) -> String {
+        let program = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .expect("Program could not be parsed correctly");
+        match evaluator::eval(&program, &mut Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let tests = [
+            ("let x=1+2*3", "let x = 1 + 2 * 3;\n"),
+            ("let   a = [1,2 ,3][0];a", "let a = [1, 2, 3][0];\na;\n"),
+            (
+                "let add=fn(a,b){a+b};add(1,-2)",
+                "let add = fn(a, b) {\n    a + b\n};\nadd(1, -2);\n",
+            ),
+            (
+                "if(x>1){return x;}else{let y=!x;y}",
+                "if (x > 1) {\n    return x;\n} else {\n    let y = !x;\n    y\n};\n",
+            ),
+            ("let f = fn() {};", "let f = fn() {};\n"),
+            (
+                "{\"a\":0x1F, true: 1_000,}",
+                "{\"a\": 0x1F, true: 1_000};\n",
+            ),
+            ("\"a\\tb\" + (1 - (2 - 3))", "\"a\\tb\" + (1 - (2 - 3));\n"),
+            ("a && b || !c <= 2.5e3 % d", "a && b || !c <= 2.5e3 % d;\n"),
+            ("", ""),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(format(input), Ok(expected.into()), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let input = "// header
+let x = 1; // one
+
+/* the
+   function */
+let f = fn(a) {
+  // inside
+  a /* value */
+  // at the end
+};
+f(x, /* arg */ 2)
+// trailing";
+        let expected = "// header
+let x = 1; // one
+
+/* the
+   function */
+let f = fn(a) {
+    // inside
+    a /* value */
+    // at the end
+};
+f(x, /* arg */ 2);
+// trailing
+";
+
+        assert_eq!(format(input), Ok(expected.into()));
+    }
+
+    #[test]
+    fn test_format_keeps_comments_inside_expressions() {
+        let tests = [
+            ("let x = /* one */ 1;", "let x = /* one */ 1;\n"),
+            ("1 +/* two */2", "1 + /* two */ 2;\n"),
+            ("[1, 2 /* end */]", "[1, 2 /* end */];\n"),
+            (
+                "{/* key */ 1: (2 /* value */)}",
+                "{/* key */ 1: (2 /* value */)};\n",
+            ),
+            ("f(1, // first\n2)", "f(1, // first\n    2);\n"),
+            ("f(1 // last\n)", "f(1 // last\n);\n"),
+            (
+                "let f = fn() {\n  g(1, // first\n  2)\n};",
+                "let f = fn() {\n    g(1, // first\n        2)\n};\n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let formatted = format(input).unwrap();
+
+            assert_eq!(formatted, expected, "input: {}", input);
+            assert_eq!(format(&formatted), Ok(formatted));
+        }
+    }
+
+    #[test]
+    fn test_format_keeps_single_blank_lines() {
+        let input = "let a = 1;\n\n\n\nlet b = 2;\nlet c = fn() {\n\n  a\n\n  b\n};";
+        let expected = "let a = 1;\n\nlet b = 2;\nlet c = fn() {\n    a;\n\n    b\n};\n";
+
+        assert_eq!(format(input), Ok(expected.into()));
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let input = "let f=fn(x){if(x<2){return x} // base\nf(x-1)+f(x-2)};\n\n/* run */ f(10)";
+        let formatted = format(input).unwrap();
+
+        assert!(formatted.contains("    if (x < 2) {\n        return x;\n    }; // base\n"));
+
+        assert_eq!(format(&formatted), Ok(formatted));
+    }
+
+    #[test]
+    fn test_format_keeps_semicolon_after_if() {
+        let tests = [
+            ("if (true) { 1 } else { 2 }; -1", "-1"),
+            ("let x = 5; if (false) { 1 }; [1, 2][0]", "1"),
+            ("let f = fn() { if (true) { 1 }; (2) }; f()", "2"),
+            ("let f = fn() { if (true) { 3 } }; f()", "3"),
+        ];
+
+        for (input, expected) in tests {
+            let formatted = format(input).unwrap();
+
+            assert_eq!(evaluate(input), expected, "input: {}", input);
+            assert_eq!(evaluate(&formatted), expected, "formatted: {}", formatted);
+            assert_eq!(format(&formatted), Ok(formatted));
+        }
+    }
+
+    #[test]
+    fn test_format_errors() {
+        assert!(format("let x = ;").is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{fmt, rc::Rc};
+
+use crate::{
+    ast::Program,
+    diagnostics::Diagnostic,
+    evaluator::{self, Builtin, BuiltinFunction, Environment, Object, RuntimeError},
+    lexer::Lexer,
+    parser::{Parser, ParserErrorList},
+};
+
+/// Evaluates programs in a global environment that is kept between calls.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    env: Environment,
+}
+
+/// Reason why source code could not be evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Parse(ParserErrorList),
+    Runtime(RuntimeError),
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Default::default()
+    }
+
+    pub fn eval_str(&mut self, source: &str) -> Result<Object, Error> {
+        let program = Parser::new(Lexer::new(source.into()))
+            .parse_program()
+            .map_err(Error::Parse)?;
+
+        self.eval_program(&program)
+    }
+
+    pub fn eval_program(&mut self, program: &Program) -> Result<Object, Error> {
+        evaluator::eval(program, &mut self.env).map_err(Error::Runtime)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.env.set(name.into(), value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.env.get(name)
+    }
+
+    /// Make a rust function callable from scripts, the span of a returned error is replaced by
+    /// the span of the call.
+    pub fn register_builtin(&mut self, name: &str, function: BuiltinFunction) {
+        self.register_fn(name, function);
+    }
+
+    /// Like [`Interpreter::register_builtin`], but the function can capture its environment.
+    pub fn register_fn<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        self.set_global(name, Object::Builtin(Builtin::new(name, function)));
+    }
+}
+
+// a closure stored in a scope it captured keeps the scope alive, such scopes are cleared here
+// unless the host still holds one of their closures. closures of other interpreters that were
+// passed in are checked again as well, but a closure that the host drops only after all
+// interpreters it was used in keeps its scopes
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        let heap = Rc::clone(self.env.get_heap());
+        let others = heap.get_other_heaps();
+
+        drop(std::mem::take(&mut self.env));
+        heap.collect();
+        for other in others {
+            other.collect();
+        }
+    }
+}
+
+impl Error {
+    pub fn to_diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::Parse(errors) => errors
+                .get_errors()
+                .iter()
+                .map(|e| e.to_diagnostic())
+                .collect(),
+            Error::Runtime(error) => vec![error.to_diagnostic()],
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => write!(f, "{}", errors),
+            Error::Runtime(error) => writeln!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        rc::{Rc, Weak},
+    };
+
+    use super::{Error, Interpreter};
+    use crate::{
+        evaluator::{FromArguments, IntoValue, Object, RuntimeError},
+        span::Span,
+    };
+
+    fn double(arguments: &[Object]) -> Result<Object, RuntimeError> {
+        match arguments {
+            [Object::Integer(value)] => Ok(Object::Integer(value * 2)),
+            _ => Err("expected one integer".into()),
+        }
+    }
+
+    #[test]
+    fn test_globals_persist_between_calls() {
+        let mut interpreter = Interpreter::new();
+
+        assert_eq!(interpreter.eval_str("let x = 5;"), Ok(Object::Null));
+        assert_eq!(interpreter.eval_str("x + 1"), Ok(Object::Integer(6)));
+        assert_eq!(interpreter.get_global("x"), Some(Object::Integer(5)));
+        assert_eq!(interpreter.get_global("y"), None);
+    }
+
+    #[test]
+    fn test_set_global() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("name", Object::String("monkey".into()));
+
+        assert_eq!(
+            interpreter.eval_str("\"hello \" + name"),
+            Ok(Object::String("hello monkey".into()))
+        );
+    }
+
+    #[test]
+    fn test_register_builtin() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_builtin("double", double);
+
+        assert_eq!(interpreter.eval_str("double(21)"), Ok(Object::Integer(42)));
+
+        let Err(Error::Runtime(error)) = interpreter.eval_str("1 + double(true)") else {
+            panic!("Expected a runtime error");
+        };
+        assert_eq!(error.get_message(), "expected one integer");
+        assert_eq!(error.to_string(), "error: expected one integer (at 4..16)");
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut interpreter = Interpreter::new();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+
+        let log = calls.clone();
+        interpreter.register_fn("log", move |arguments| {
+            let (message,): (String,) = FromArguments::from_arguments(arguments)?;
+            log.borrow_mut().push(message);
+            Ok(().into_value())
+        });
+        interpreter.register_fn("average", |arguments| {
+            let (values,): (Vec<f64>,) = FromArguments::from_arguments(arguments)?;
+            if values.is_empty() {
+                return Err("cannot average an empty array".into());
+            }
+            Ok((values.iter().sum::<f64>() / values.len() as f64).into_value())
+        });
+        interpreter.register_fn("count", |arguments| {
+            let (words,): (Vec<String>,) = FromArguments::from_arguments(arguments)?;
+            let mut counts: HashMap<String, i64> = HashMap::new();
+            for word in words {
+                *counts.entry(word).or_default() += 1;
+            }
+            Ok(counts.into_value())
+        });
+
+        assert_eq!(
+            interpreter.eval_str("log(\"a\"); log(\"b\"); average([1, 2.5, 3])"),
+            Ok(Object::Float(6.5 / 3.0))
+        );
+        assert_eq!(*calls.borrow(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            interpreter.eval_str("count([\"a\", \"b\", \"a\"])[\"a\"]"),
+            Ok(Object::Integer(2))
+        );
+
+        let tests = [
+            (
+                "let x = average([]);",
+                "cannot average an empty array",
+                Span::new(8, 19),
+            ),
+            (
+                "log(\"a\", 1)",
+                "wrong number of arguments: want=1, got=2",
+                Span::new(0, 11),
+            ),
+            (
+                "1 + average([true])",
+                "argument 1: expected FLOAT, got BOOLEAN",
+                Span::new(4, 19),
+            ),
+        ];
+
+        for (input, message, span) in tests {
+            let Err(Error::Runtime(error)) = interpreter.eval_str(input) else {
+                panic!("Expected a runtime error for {}", input);
+            };
+            assert_eq!(
+                error,
+                RuntimeError::new(message.into(), span),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::new();
+
+        let error = interpreter.eval_str("let = 1;").unwrap_err();
+        assert!(matches!(error, Error::Parse(_)));
+        assert_eq!(error.to_diagnostics().len(), 1);
+
+        let error = interpreter.eval_str("1 + true").unwrap_err();
+        assert!(matches!(error, Error::Runtime(_)));
+        assert_eq!(
+            error.to_string(),
+            "error: type mismatch: INTEGER + BOOLEAN (at 0..8)\n"
+        );
+    }
+
+    #[test]
+    fn test_drop_frees_closures_that_capture_themselves() {
+        let mut interpreter = Interpreter::new();
+        let heap = Rc::downgrade(interpreter.env.get_heap());
+
+        let input = "
+            let f = fn() { f };
+            let g = fn() { let h = fn() { h }; h };
+            let loop = fn(n) { if (n > 0) { g(); loop(n - 1) } };
+            loop(200);
+            let a = [fn() { a }];
+            let b = {\"b\": fn() { b }};
+            let h = g();
+        ";
+        assert_eq!(interpreter.eval_str(input), Ok(Object::Null));
+        // only the global scope and the scope of the call that returned `h` are left
+        assert_eq!(heap.upgrade().unwrap().collect(), 2);
+
+        drop(interpreter);
+        assert!(heap.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_drop_keeps_closures_held_by_the_host() {
+        let mut first = Interpreter::new();
+        let heap = Rc::downgrade(first.env.get_heap());
+        let f = first
+            .eval_str("let x = 2; let f = fn(n) { if (n == 0) { 0 } else { x + f(n - 1) } }; f")
+            .unwrap();
+        drop(first);
+
+        let mut second = Interpreter::new();
+        second.set_global("f", f);
+        assert_eq!(second.eval_str("f(3)"), Ok(Object::Integer(6)));
+
+        drop(second);
+        assert!(Weak::upgrade(&heap).is_none());
+    }
+}
@@ -1,11 +1,28 @@
-use crate::token::{Token, TokenType};
+use crate::{
+    span::Span,
+    token::{Number, Token, TokenType},
+};
 
+/// Identifiers that are reserved by the language.
+pub const KEYWORDS: [(&str, TokenType); 7] = [
+    ("fn", TokenType::Function),
+    ("let", TokenType::Let),
+    ("true", TokenType::True),
+    ("false", TokenType::False),
+    ("if", TokenType::If),
+    ("else", TokenType::Else),
+    ("return", TokenType::Return),
+];
+
+/// Reads the input char by char, `position` and `read_position` are byte offsets of the current
+/// and the next char, so that the input can be sliced directly.
 #[derive(Default, Debug, Clone)]
 pub struct Lexer {
     input: String,
     position: usize,
     read_position: usize,
     ch: Option<char>,
+    keep_comments: bool,
 }
 
 impl Lexer {
@@ -19,111 +36,388 @@ impl Lexer {
         lexer
     }
 
-    fn highlighted_input(input: String, token: &Token) -> String {
-        let mut context = input.to_string();
-        context.insert(token.get_position().0, '>');
-        context.insert(token.get_position().1 + 1, '<');
-        context
+    /// Return comments as `Comment` tokens instead of skipping them, so tools like a formatter can
+    /// preserve them.
+    pub fn with_comments(mut self) -> Lexer {
+        self.keep_comments = true;
+        self
     }
 
     fn read_char(&mut self) {
-        self.ch = self.input.chars().nth(self.read_position);
         self.position = self.read_position;
-        self.read_position += 1;
+        self.ch = self.peek_char();
+
+        if let Some(ch) = self.ch {
+            self.read_position += ch.len_utf8();
+        }
     }
 
     fn peek_char(&self) -> Option<char> {
-        self.input.chars().nth(self.read_position)
+        self.input[self.read_position..].chars().next()
     }
 
-    pub fn next_token(&mut self) -> Token {
-        // whitespaces have no meaning for now - done here to keep not concat variable names etc.
+    fn read_string(&mut self) -> Token {
+        let start_pos = self.position;
+        let mut value = String::new();
+        let mut error = None;
+
+        // skip the opening quote
+        self.read_char();
+        loop {
+            match self.ch {
+                None => {
+                    return self.new_token(
+                        TokenType::Illegal,
+                        "unterminated string literal".into(),
+                        start_pos,
+                    )
+                }
+                Some('"') => break,
+                Some('\\') => {
+                    self.read_char();
+                    match self.read_escape_sequence() {
+                        Ok(ch) => value.push(ch),
+                        // keep reading until the closing quote to not lex the rest as code
+                        Err(message) => {
+                            error.get_or_insert(message);
+                        }
+                    }
+                }
+                Some(ch) => {
+                    value.push(ch);
+                    self.read_char();
+                }
+            }
+        }
+
+        // skip the closing quote
+        self.read_char();
+
+        match error {
+            Some(message) => self.new_token(TokenType::Illegal, message, start_pos),
+            None => self.new_token(TokenType::String, value, start_pos),
+        }
+    }
+
+    /// Read the escape sequence after a backslash, leaving the lexer on the first character
+    /// after it.
+    fn read_escape_sequence(&mut self) -> Result<char, String> {
+        let escaped = match self.ch {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('u') => return self.read_unicode_escape_sequence(),
+            Some(ch) => {
+                self.read_char();
+                return Err(format!("invalid escape sequence: \\{}", ch));
+            }
+            None => return Err("unterminated string literal".into()),
+        };
+
+        self.read_char();
+        Ok(escaped)
+    }
+
+    fn read_unicode_escape_sequence(&mut self) -> Result<char, String> {
+        let invalid = || "invalid unicode escape sequence, expected \\u{...}".to_string();
+
+        // skip the `u`
+        self.read_char();
+        if self.ch != Some('{') {
+            return Err(invalid());
+        }
+        self.read_char();
+
+        let mut digits = String::new();
         while let Some(ch) = self.ch {
-            if !matches!(ch, '\n' | '\r' | '\t' | ' ') {
+            if !ch.is_ascii_hexdigit() {
                 break;
             }
+            digits.push(ch);
+            self.read_char();
+        }
+
+        if self.ch != Some('}') || digits.is_empty() || digits.len() > 6 {
+            return Err(invalid());
+        }
+        self.read_char();
+
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("invalid unicode code point: {}", digits))
+    }
+
+    /// Read an integer (decimal or with a `0x`, `0o` or `0b` prefix) or a float with an optional
+    /// exponent, digits can be separated by `_`. The literal of the token is the normalized value.
+    fn read_number(&mut self) -> Token {
+        let start_pos = self.position;
+
+        let radix = match (self.ch, self.peek_char()) {
+            (Some('0'), Some('x')) => Some((16, "hexadecimal")),
+            (Some('0'), Some('o')) => Some((8, "octal")),
+            (Some('0'), Some('b')) => Some((2, "binary")),
+            _ => None,
+        };
+
+        let result = match radix {
+            Some((radix, name)) => {
+                // skip the prefix
+                self.read_char();
+                self.read_char();
+                self.read_radix_integer(radix, name)
+            }
+            None => self.read_decimal_number(start_pos),
+        };
+
+        // a number directly followed by a name is most likely a typo
+        let result = match self.ch {
+            Some('a'..='z' | 'A'..='Z' | '_') if result.is_ok() => {
+                let suffix_pos = self.position;
+                self.skip_number_chars();
+                Err(format!(
+                    "invalid suffix on number literal: {}",
+                    &self.input[suffix_pos..self.position]
+                ))
+            }
+            _ => result,
+        };
+
+        match result {
+            Ok(Number::Int(value)) => self
+                .new_token(TokenType::Int, value.to_string(), start_pos)
+                .with_number(Number::Int(value)),
+            Ok(Number::Float(value)) => self
+                .new_token(TokenType::Float, value.to_string(), start_pos)
+                .with_number(Number::Float(value)),
+            Err(message) => self.new_token(TokenType::Illegal, message, start_pos),
+        }
+    }
+
+    fn read_radix_integer(&mut self, radix: u32, name: &str) -> Result<Number, String> {
+        let digits_pos = self.position;
+        self.skip_number_chars();
+
+        let digits = self.input[digits_pos..self.position].replace('_', "");
+        if digits.is_empty() {
+            return Err(format!("missing digits in {} literal", name));
+        }
+
+        if let Some(invalid) = digits.chars().find(|ch| !ch.is_digit(radix)) {
+            return Err(format!("invalid digit in {} literal: {}", name, invalid));
+        }
+
+        i64::from_str_radix(&digits, radix)
+            .map(Number::Int)
+            .map_err(|_| "integer literal is too large".to_string())
+    }
+
+    fn read_decimal_number(&mut self, start_pos: usize) -> Result<Number, String> {
+        let mut is_float = false;
+        self.skip_digits();
+
+        if self.ch == Some('.') {
+            is_float = true;
+            self.read_char();
+
+            if !matches!(self.ch, Some('0'..='9')) {
+                self.skip_number_chars();
+                return Err("missing digits after the decimal point".into());
+            }
+            self.skip_digits();
+
+            if self.ch == Some('.') {
+                // consume the whole malformed number to report it once
+                while matches!(self.ch, Some('0'..='9' | '_' | '.')) {
+                    self.read_char();
+                }
+                return Err("number literal with more than one decimal point".into());
+            }
+        }
+
+        if matches!(self.ch, Some('e' | 'E')) {
+            is_float = true;
+            self.read_char();
+
+            if matches!(self.ch, Some('+' | '-')) {
+                self.read_char();
+            }
+
+            if !matches!(self.ch, Some('0'..='9')) {
+                return Err("missing digits in the exponent".into());
+            }
+            self.skip_digits();
+        }
+
+        let literal = self.input[start_pos..self.position].replace('_', "");
+        if is_float {
+            literal
+                .parse::<f64>()
+                .map(Number::Float)
+                .map_err(|_| format!("invalid float literal: {}", literal))
+        } else {
+            literal
+                .parse::<i64>()
+                .map(Number::Int)
+                .map_err(|_| "integer literal is too large".to_string())
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while matches!(self.ch, Some('0'..='9' | '_')) {
             self.read_char();
         }
+    }
+
+    fn skip_number_chars(&mut self) {
+        while matches!(self.ch, Some('a'..='z' | 'A'..='Z' | '0'..='9' | '_')) {
+            self.read_char();
+        }
+    }
+
+    /// Read a `//` comment up to the end of the line, the newline is not part of the comment.
+    fn read_line_comment(&mut self) -> Token {
+        let start_pos = self.position;
+        while !matches!(self.ch, None | Some('\n')) {
+            self.read_char();
+        }
+
+        let comment = self.input[start_pos..self.position].to_string();
+        self.new_token(TokenType::Comment, comment, start_pos)
+    }
+
+    /// Read a `/* */` comment, which can contain other block comments.
+    fn read_block_comment(&mut self) -> Token {
+        let start_pos = self.position;
+        let mut depth = 0;
+
+        loop {
+            match (self.ch, self.peek_char()) {
+                (None, _) => {
+                    return self.new_token(
+                        TokenType::Illegal,
+                        "unterminated block comment".into(),
+                        start_pos,
+                    )
+                }
+                (Some('/'), Some('*')) => {
+                    depth += 1;
+                    self.read_char();
+                }
+                (Some('*'), Some('/')) => {
+                    depth -= 1;
+                    self.read_char();
+                    if depth == 0 {
+                        self.read_char();
+                        break;
+                    }
+                }
+                _ => {}
+            }
+
+            self.read_char();
+        }
+
+        let comment = self.input[start_pos..self.position].to_string();
+        self.new_token(TokenType::Comment, comment, start_pos)
+    }
+
+    /// Create a token that ends right before the current character.
+    fn new_token(&self, r#type: TokenType, literal: String, start_pos: usize) -> Token {
+        Token::new(r#type, literal, Span::new(start_pos, self.position))
+    }
+
+    pub fn next_token(&mut self) -> Token {
+        loop {
+            // whitespaces have no meaning for now - done here to keep not concat variable names etc.
+            while let Some(ch) = self.ch {
+                if !matches!(ch, '\n' | '\r' | '\t' | ' ') {
+                    break;
+                }
+                self.read_char();
+            }
+
+            let comment = match (self.ch, self.peek_char()) {
+                (Some('/'), Some('/')) => self.read_line_comment(),
+                (Some('/'), Some('*')) => self.read_block_comment(),
+                _ => break,
+            };
+
+            if self.keep_comments || comment.get_type() == TokenType::Illegal {
+                return comment;
+            }
+        }
 
         // store start postition even after reading more characters
         let start_pos = self.position;
-        let token = match self.ch {
-            None => Token::new(TokenType::Eof, "".into(), start_pos),
-            Some(';') => Token::new(TokenType::Semicolon, ";".into(), start_pos),
-            Some('(') => Token::new(TokenType::Lparen, "(".into(), start_pos),
-            Some(')') => Token::new(TokenType::Rparen, ")".into(), start_pos),
-            Some('{') => Token::new(TokenType::Lbrace, "{".into(), start_pos),
-            Some('}') => Token::new(TokenType::Rbrace, "}".into(), start_pos),
-            Some(',') => Token::new(TokenType::Comma, ",".into(), start_pos),
-            Some('+') => Token::new(TokenType::Plus, "+".into(), start_pos),
-            Some('-') => Token::new(TokenType::Minus, "-".into(), start_pos),
-            Some('*') => Token::new(TokenType::Asterisk, "*".into(), start_pos),
-            Some('/') => Token::new(TokenType::Slash, "/".into(), start_pos),
-            Some('<') => Token::new(TokenType::Lt, "<".into(), start_pos),
-            Some('>') => Token::new(TokenType::Gt, ">".into(), start_pos),
-            Some('=') => {
+        let (r#type, literal) = match self.ch {
+            None => return self.new_token(TokenType::Eof, "".into(), start_pos),
+            Some(';') => (TokenType::Semicolon, ";".into()),
+            Some('(') => (TokenType::Lparen, "(".into()),
+            Some(')') => (TokenType::Rparen, ")".into()),
+            Some('{') => (TokenType::Lbrace, "{".into()),
+            Some('}') => (TokenType::Rbrace, "}".into()),
+            Some('[') => (TokenType::Lbracket, "[".into()),
+            Some(']') => (TokenType::Rbracket, "]".into()),
+            Some(',') => (TokenType::Comma, ",".into()),
+            Some(':') => (TokenType::Colon, ":".into()),
+            Some('+') => (TokenType::Plus, "+".into()),
+            Some('-') => (TokenType::Minus, "-".into()),
+            Some('*') => (TokenType::Asterisk, "*".into()),
+            Some('/') => (TokenType::Slash, "/".into()),
+            Some('%') => (TokenType::Percent, "%".into()),
+            Some('<') => {
                 if let Some('=') = self.peek_char() {
                     self.read_char();
-                    Token::new(TokenType::Eq, "==".into(), start_pos)
+                    (TokenType::LtEq, "<=".into())
                 } else {
-                    Token::new(TokenType::Assign, "=".into(), start_pos)
+                    (TokenType::Lt, "<".into())
                 }
             }
-            Some('!') => {
+            Some('>') => {
                 if let Some('=') = self.peek_char() {
                     self.read_char();
-                    Token::new(TokenType::NotEq, "!=".into(), start_pos)
+                    (TokenType::GtEq, ">=".into())
                 } else {
-                    Token::new(TokenType::Bang, "!".into(), start_pos)
+                    (TokenType::Gt, ">".into())
                 }
             }
-
-            Some('0'..='9') => {
-                enum NumberType {
-                    Int,
-                    Float,
+            Some('=') => {
+                if let Some('=') = self.peek_char() {
+                    self.read_char();
+                    (TokenType::Eq, "==".into())
+                } else {
+                    (TokenType::Assign, "=".into())
                 }
-
-                let mut number_type = NumberType::Int;
-
-                let start_pos = self.position;
-                while let Some(ch) = self.ch {
-                    if ch == '.' {
-                        number_type = NumberType::Float;
-                    }
-
-                    if !ch.is_ascii_digit() && ch != '.' {
-                        break;
-                    }
-
+            }
+            Some('!') => {
+                if let Some('=') = self.peek_char() {
                     self.read_char();
+                    (TokenType::NotEq, "!=".into())
+                } else {
+                    (TokenType::Bang, "!".into())
                 }
+            }
+            Some('&') if self.peek_char() == Some('&') => {
+                self.read_char();
+                (TokenType::And, "&&".into())
+            }
+            Some('|') if self.peek_char() == Some('|') => {
+                self.read_char();
+                (TokenType::Or, "||".into())
+            }
 
-                let literal = self.input[start_pos..self.position].to_string();
-                let token = match number_type {
-                    NumberType::Int => Token::new(
-                        TokenType::Int,
-                        // remove leading 0s
-                        literal.trim_start_matches('0').to_string(),
-                        start_pos,
-                    ),
-                    NumberType::Float => Token::new(
-                        TokenType::Float,
-                        // remove trailing 0s and 0s after 0. and 0.0
-                        literal
-                            .trim_end_matches('0')
-                            .trim_end_matches('.')
-                            .to_string(),
-                        start_pos,
-                    ),
-                };
-
+            Some('"') => {
                 // need to return to not skip the next token
-                return token;
+                return self.read_string();
+            }
+            Some('0'..='9') => {
+                // need to return to not skip the next token
+                return self.read_number();
             }
             Some('a'..='z' | 'A'..='Z' | '_') => {
                 // read identifier, allow snake_case and numbers (not at start)
-                let start_pos = self.position;
                 while let Some(ch) = self.ch {
                     if !matches!(ch, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_') {
                         break;
@@ -132,43 +426,38 @@ impl Lexer {
                 }
 
                 let identifier = self.input[start_pos..self.position].to_string();
-                let token = match identifier.as_str() {
-                    "fn" => Token::new(TokenType::Function, "fn".into(), start_pos),
-                    "let" => Token::new(TokenType::Let, "let".into(), start_pos),
-                    "true" => Token::new(TokenType::True, "true".into(), start_pos),
-                    "false" => Token::new(TokenType::False, "false".into(), start_pos),
-                    "if" => Token::new(TokenType::If, "if".into(), start_pos),
-                    "else" => Token::new(TokenType::Else, "else".into(), start_pos),
-                    "return" => Token::new(TokenType::Return, "return".into(), start_pos),
-                    _ => Token::new(TokenType::Ident, identifier, start_pos),
-                };
+                let r#type = KEYWORDS
+                    .iter()
+                    .find(|(keyword, _)| *keyword == identifier)
+                    .map_or(TokenType::Ident, |(_, r#type)| r#type.clone());
 
                 // need to return to not skip the next token
-                return token;
+                return self.new_token(r#type, identifier, start_pos);
             }
-            Some(ch) => Token::new(TokenType::Illegal, ch.to_string(), start_pos),
+            Some(ch) => (TokenType::Illegal, format!("unexpected character: {}", ch)),
         };
 
         self.read_char();
-        token
+        self.new_token(r#type, literal, start_pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        diagnostics::Diagnostic,
         lexer::Lexer,
-        token::{Token, TokenType},
+        span::Span,
+        token::{Number, Token, TokenType},
     };
 
     fn context_formatting(input: String, token: &Token) -> String {
-        format!(
-            "\n\tTYPE: {:?}\n\tLITERAL: {:?}\n\tPOSITION: {:?}\n\tCONTEXT:\n{}\n",
-            token.get_type(),
-            token.get_literal(),
-            token.get_position(),
-            Lexer::highlighted_input(input, token)
-        )
+        let diagnostic = Diagnostic::new(
+            format!("{:?} {:?}", token.get_type(), token.get_literal()),
+            token.get_span(),
+        );
+
+        format!("\n{}", diagnostic.render("test", &input, false))
     }
 
     #[test]
@@ -196,8 +485,8 @@ mod tests {
 
             if token.get_literal() == "this_is_wanted" {
                 assert_eq!(
-                    token.get_position(),
-                    (114, 128),
+                    token.get_span(),
+                    Span::new(114, 128),
                     "{}",
                     context_formatting(input.to_string(), &token)
                 )
@@ -205,8 +494,8 @@ mod tests {
 
             if token.get_type() == TokenType::True {
                 assert_eq!(
-                    token.get_position(),
-                    (226, 230),
+                    token.get_span(),
+                    Span::new(226, 230),
                     "{}",
                     context_formatting(input.to_string(), &token)
                 )
@@ -227,8 +516,8 @@ mod tests {
 
             if token.get_type() == TokenType::Rparen {
                 assert_eq!(
-                    token.get_position(),
-                    (1, 2),
+                    token.get_span(),
+                    Span::new(1, 2),
                     "{}",
                     context_formatting(input.to_string(), &token)
                 )
@@ -238,7 +527,7 @@ mod tests {
 
     #[test]
     fn test_token_meaning_simple() {
-        let input = "=+-<>!*/(){},;";
+        let input = "=+-<>!*/%(){}[],:;";
         let tests = vec![
             TokenType::Assign,
             TokenType::Plus,
@@ -248,11 +537,15 @@ mod tests {
             TokenType::Bang,
             TokenType::Asterisk,
             TokenType::Slash,
+            TokenType::Percent,
             TokenType::Lparen,
             TokenType::Rparen,
             TokenType::Lbrace,
             TokenType::Rbrace,
+            TokenType::Lbracket,
+            TokenType::Rbracket,
             TokenType::Comma,
+            TokenType::Colon,
             TokenType::Semicolon,
             TokenType::Eof,
         ];
@@ -421,7 +714,7 @@ mod tests {
 
             let result = add(five, ten);
 
-            !-/*5;
+            !-/ *5;
             5 < 10 > 5;
 
             if (5 < 10) {
@@ -529,4 +822,419 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_string() {
+        let input = r#"let s = "foo bar"; "";"#;
+        let tests = vec![
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "s"),
+            (TokenType::Assign, "="),
+            (TokenType::String, "foo bar"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::String, ""),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Eof, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                token.get_type(),
+                test.0,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+
+            assert_eq!(
+                token.get_literal(),
+                test.1,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_escape_sequences() {
+        let input = r#""a\nb\tc\"d\\e\u{41}\u{1F600}""#;
+
+        let mut l = Lexer::new(input.to_string());
+        let token = l.next_token();
+
+        assert_eq!(token.get_type(), TokenType::String);
+        assert_eq!(token.get_literal(), "a\nb\tc\"d\\eA\u{1F600}");
+        assert_eq!(l.next_token().get_type(), TokenType::Eof);
+    }
+
+    #[test]
+    fn test_string_position() {
+        let input = r#"x = "a\tb";"#;
+
+        let mut l = Lexer::new(input.to_string());
+        l.next_token();
+        l.next_token();
+        let token = l.next_token();
+
+        assert_eq!(
+            token.get_span(),
+            Span::new(4, 10),
+            "{}",
+            context_formatting(input.to_string(), &token)
+        );
+    }
+
+    #[test]
+    fn test_string_errors() {
+        let tests = [
+            (r#""foo"#, "unterminated string literal", (0, 4)),
+            (r#""foo\"#, "unterminated string literal", (0, 5)),
+            (r#""a\qb""#, "invalid escape sequence: \\q", (0, 6)),
+            (
+                r#""\u{zz}""#,
+                "invalid unicode escape sequence, expected \\u{...}",
+                (0, 8),
+            ),
+            (r#""\u{D800}""#, "invalid unicode code point: D800", (0, 10)),
+        ];
+
+        for (input, message, position) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let token = l.next_token();
+
+            assert_eq!(token.get_type(), TokenType::Illegal, "input: {}", input);
+            assert_eq!(token.get_literal(), message, "input: {}", input);
+            assert_eq!(
+                token.get_span(),
+                Span::new(position.0, position.1),
+                "input: {}",
+                input
+            );
+            assert_eq!(
+                l.next_token().get_type(),
+                TokenType::Eof,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// header
+let x = 5; // trailing
+/* block */ x /* nested /* inner */ still comment */ / 2
+/**/ // end";
+        let tests = vec![
+            (TokenType::Let, Span::new(10, 13)),
+            (TokenType::Ident, Span::new(14, 15)),
+            (TokenType::Assign, Span::new(16, 17)),
+            (TokenType::Int, Span::new(18, 19)),
+            (TokenType::Semicolon, Span::new(19, 20)),
+            (TokenType::Ident, Span::new(45, 46)),
+            (TokenType::Slash, Span::new(86, 87)),
+            (TokenType::Int, Span::new(88, 89)),
+            (TokenType::Eof, Span::new(101, 101)),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                (token.get_type(), token.get_span()),
+                test,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    #[test]
+    fn test_comments_as_tokens() {
+        let input = "x // line\n/* a /* b */ */ y";
+        let tests = vec![
+            (TokenType::Ident, "x"),
+            (TokenType::Comment, "// line"),
+            (TokenType::Comment, "/* a /* b */ */"),
+            (TokenType::Ident, "y"),
+            (TokenType::Eof, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string()).with_comments();
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!((token.get_type(), token.get_literal().as_str()), test);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let tests = [
+            ("/* open", (0, 7)),
+            ("x /* a /* b */", (2, 14)),
+            ("/*/", (0, 3)),
+        ];
+
+        for (input, position) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut token = l.next_token();
+            if token.get_type() == TokenType::Ident {
+                token = l.next_token();
+            }
+
+            assert_eq!(token.get_type(), TokenType::Illegal, "input: {}", input);
+            assert_eq!(token.get_literal(), "unterminated block comment");
+            assert_eq!(
+                token.get_span(),
+                Span::new(position.0, position.1),
+                "input: {}",
+                input
+            );
+            assert_eq!(l.next_token().get_type(), TokenType::Eof);
+        }
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let tests = [
+            ("0", Number::Int(0)),
+            ("000", Number::Int(0)),
+            ("1_000_000", Number::Int(1_000_000)),
+            ("0x1F", Number::Int(31)),
+            ("0xff_ff", Number::Int(65535)),
+            ("0o17", Number::Int(15)),
+            ("0b1010", Number::Int(10)),
+            ("9223372036854775807", Number::Int(i64::MAX)),
+            ("0.0", Number::Float(0.0)),
+            ("1_000.5", Number::Float(1000.5)),
+            ("1.5e-3", Number::Float(0.0015)),
+            ("2E10", Number::Float(2e10)),
+            ("1e+2", Number::Float(100.0)),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let token = l.next_token();
+
+            assert_eq!(token.get_number(), Some(expected), "input: {}", input);
+            assert_eq!(token.get_span(), Span::new(0, input.len()));
+            assert_eq!(
+                l.next_token().get_type(),
+                TokenType::Eof,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_number_errors() {
+        let tests = [
+            (
+                "1.2.3",
+                "number literal with more than one decimal point",
+                5,
+            ),
+            ("3.", "missing digits after the decimal point", 2),
+            ("3.e5", "missing digits after the decimal point", 4),
+            ("1e", "missing digits in the exponent", 2),
+            ("1e+", "missing digits in the exponent", 3),
+            ("0x", "missing digits in hexadecimal literal", 2),
+            ("0b102", "invalid digit in binary literal: 2", 5),
+            ("0o8", "invalid digit in octal literal: 8", 3),
+            ("9223372036854775808", "integer literal is too large", 19),
+            ("0xFFFFFFFFFFFFFFFFF", "integer literal is too large", 19),
+            ("12abc", "invalid suffix on number literal: abc", 5),
+        ];
+
+        for (input, message, end) in tests {
+            let mut l = Lexer::new(format!("{};", input));
+            let token = l.next_token();
+
+            assert_eq!(token.get_type(), TokenType::Illegal, "input: {}", input);
+            assert_eq!(token.get_literal(), message, "input: {}", input);
+            assert_eq!(token.get_span(), Span::new(0, end), "input: {}", input);
+            assert_eq!(
+                l.next_token().get_type(),
+                TokenType::Semicolon,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_span_of_normalized_numbers() {
+        let input = "001230 3.140 0.0";
+        let tests = vec![
+            (TokenType::Int, "1230", Span::new(0, 6)),
+            (TokenType::Float, "3.14", Span::new(7, 12)),
+            (TokenType::Float, "0", Span::new(13, 16)),
+            (TokenType::Eof, "", Span::new(16, 16)),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                (
+                    token.get_type(),
+                    token.get_literal().as_str(),
+                    token.get_span()
+                ),
+                test,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    #[test]
+    fn test_span_of_operators() {
+        let input = "a == !b != c";
+        let tests = vec![
+            (TokenType::Ident, Span::new(0, 1)),
+            (TokenType::Eq, Span::new(2, 4)),
+            (TokenType::Bang, Span::new(5, 6)),
+            (TokenType::Ident, Span::new(6, 7)),
+            (TokenType::NotEq, Span::new(8, 10)),
+            (TokenType::Ident, Span::new(11, 12)),
+            (TokenType::Eof, Span::new(12, 12)),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                (token.get_type(), token.get_span()),
+                test,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    #[test]
+    fn test_two_char_operators() {
+        let input = "a<=b >= c&&d || !e & f";
+        let tests = vec![
+            (TokenType::Ident, "a", Span::new(0, 1)),
+            (TokenType::LtEq, "<=", Span::new(1, 3)),
+            (TokenType::Ident, "b", Span::new(3, 4)),
+            (TokenType::GtEq, ">=", Span::new(5, 7)),
+            (TokenType::Ident, "c", Span::new(8, 9)),
+            (TokenType::And, "&&", Span::new(9, 11)),
+            (TokenType::Ident, "d", Span::new(11, 12)),
+            (TokenType::Or, "||", Span::new(13, 15)),
+            (TokenType::Bang, "!", Span::new(16, 17)),
+            (TokenType::Ident, "e", Span::new(17, 18)),
+            (
+                TokenType::Illegal,
+                "unexpected character: &",
+                Span::new(19, 20),
+            ),
+            (TokenType::Ident, "f", Span::new(21, 22)),
+            (TokenType::Eof, "", Span::new(22, 22)),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                (
+                    token.get_type(),
+                    token.get_literal().as_str(),
+                    token.get_span()
+                ),
+                test,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let input = "let s = \"äöü 😀\"; ä + s";
+        let tests = vec![
+            (TokenType::Let, "let", Span::new(0, 3)),
+            (TokenType::Ident, "s", Span::new(4, 5)),
+            (TokenType::Assign, "=", Span::new(6, 7)),
+            (TokenType::String, "äöü 😀", Span::new(8, 21)),
+            (TokenType::Semicolon, ";", Span::new(21, 22)),
+            (
+                TokenType::Illegal,
+                "unexpected character: ä",
+                Span::new(23, 25),
+            ),
+            (TokenType::Plus, "+", Span::new(26, 27)),
+            (TokenType::Ident, "s", Span::new(28, 29)),
+            (TokenType::Eof, "", Span::new(29, 29)),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for test in tests {
+            let token = l.next_token();
+            assert_eq!(
+                (
+                    token.get_type(),
+                    token.get_literal().as_str(),
+                    token.get_span()
+                ),
+                test,
+                "{}",
+                context_formatting(input.to_string(), &token)
+            );
+        }
+    }
+
+    fn generate_source(min_len: usize) -> String {
+        let chunk = "
+            let add_them_all = fn(x, y) { if (x < y) { return \"smaller ✓\"; } x + y * 3.14 };
+            let result = add_them_all(1234, 5678) != !true;
+        ";
+
+        chunk.repeat(min_len / chunk.len() + 1)
+    }
+
+    fn count_tokens(input: String) -> usize {
+        let mut l = Lexer::new(input);
+        let mut count = 0;
+        while l.next_token().get_type() != TokenType::Eof {
+            count += 1;
+        }
+
+        count
+    }
+
+    #[test]
+    fn test_multi_megabyte_input() {
+        let input = generate_source(2 * 1024 * 1024);
+        let chunks = input.matches("add_them_all = fn").count();
+
+        assert_eq!(count_tokens(input), chunks * 41);
+    }
+
+    /// Run with `make bench`.
+    #[test]
+    #[ignore]
+    fn bench_lexer_throughput() {
+        for megabytes in [1, 4, 16] {
+            let input = generate_source(megabytes * 1024 * 1024);
+            let len = input.len();
+
+            let start = std::time::Instant::now();
+            let tokens = count_tokens(input);
+            let elapsed = start.elapsed();
+
+            println!(
+                "{:>2} MB: {:>9} tokens in {:>8.2?} ({:.1} MB/s)",
+                megabytes,
+                tokens,
+                elapsed,
+                len as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64()
+            );
+        }
+    }
 }
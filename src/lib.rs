@@ -0,0 +1,56 @@
+//! Interpreter for the Monkey language.
+//!
+//! [`Interpreter`] runs source code and keeps its globals between calls, the [`Lexer`] and
+//! [`Parser`] can be used on their own to inspect the tokens or the syntax tree.
+//!
+//! ```
+//! use toy_interpreter::{Interpreter, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.set_global("x", Value::Integer(20));
+//!
+//! assert_eq!(interpreter.eval_str("x * 2 + 2"), Ok(Value::Integer(42)));
+//! ```
+//!
+//! Rust functions can be called from scripts once they are registered, [`FromArguments`] and
+//! [`IntoValue`] convert between script values and rust types:
+//!
+//! ```
+//! use toy_interpreter::{FromArguments, Interpreter, IntoValue, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.register_fn("repeat", |arguments: &[Value]| {
+//!     let (text, count): (String, i64) = FromArguments::from_arguments(arguments)?;
+//!     Ok(text.repeat(count.max(0) as usize).into_value())
+//! });
+//!
+//! assert_eq!(
+//!     interpreter.eval_str("repeat(\"ab\", 3)"),
+//!     Ok(Value::String("ababab".into()))
+//! );
+//! ```
+
+pub mod ast;
+pub mod compiler;
+#[cfg(test)]
+mod conformance;
+pub mod diagnostics;
+mod evaluator;
+pub mod formatter;
+mod interpreter;
+mod lexer;
+mod parser;
+mod span;
+mod token;
+pub mod vm;
+
+pub use ast::Program;
+pub use evaluator::{
+    Builtin, BuiltinFunction, Closure, FromArguments, FromValue, Function, HashKey, HashPair,
+    IntoValue, Object as Value, RuntimeError,
+};
+pub use interpreter::{Error, Interpreter};
+pub use lexer::{Lexer, KEYWORDS};
+pub use parser::{Parser, ParserError, ParserErrorList};
+pub use span::{Location, SourceMap, Span};
+pub use token::{Token, TokenType};
@@ -0,0 +1,477 @@
+mod analysis;
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+use serde_json::{json, Value};
+use toy_interpreter::{Span, KEYWORDS};
+
+use analysis::{analyze, Analysis, BindingKind, Symbol};
+
+// error codes defined by JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Open document, it is analyzed again on every change.
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+/// Language server state, kept free of any IO so it can be driven by the tests.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: bool,
+}
+
+impl Server {
+    /// Handle one message from the client and return the messages to send back.
+    fn handle(&mut self, body: &[u8]) -> Vec<Value> {
+        let message: Value = match serde_json::from_slice(body) {
+            Ok(message) => message,
+            Err(e) => return vec![error_response(Value::Null, PARSE_ERROR, &e.to_string())],
+        };
+
+        let Some(method) = message["method"].as_str() else {
+            // responses to requests of the server, which does not send any
+            if message.get("id").is_some() && message.get("result").is_none() {
+                return vec![error_response(
+                    message["id"].clone(),
+                    INVALID_REQUEST,
+                    "missing method",
+                )];
+            }
+            return Vec::new();
+        };
+        let params = &message["params"];
+
+        // notifications do not have an id and are never answered
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+        // after a shutdown only the exit notification is expected
+        if self.shutdown {
+            return vec![error_response(
+                id,
+                INVALID_REQUEST,
+                "the server is shut down",
+            )];
+        }
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "documentSymbolProvider": true,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "toy-interpreter" },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/documentSymbol" => match self.document(params) {
+                Some(document) => Value::Array(
+                    document
+                        .analysis
+                        .get_symbols()
+                        .iter()
+                        .map(|symbol| to_document_symbol(&document.text, symbol))
+                        .collect(),
+                ),
+                None => Value::Null,
+            },
+            "textDocument/definition" => self
+                .find_binding(params)
+                .map_or(Value::Null, |(document, binding)| {
+                    json!({
+                        "uri": params["textDocument"]["uri"],
+                        "range": to_range(&document.text, binding.get_span()),
+                    })
+                }),
+            "textDocument/hover" => self
+                .find_binding(params)
+                .map_or(Value::Null, |(_, binding)| {
+                    json!({ "contents": { "kind": "plaintext", "value": binding.describe() } })
+                }),
+            "textDocument/completion" => Value::Array(
+                KEYWORDS
+                    .iter()
+                    .map(|(keyword, _)| json!({ "label": keyword, "kind": 14 }))
+                    .collect(),
+            ),
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("unknown method {}", method),
+                )]
+            }
+        };
+
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        let text = match method {
+            "exit" => {
+                self.exit = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // the full text is sent on every change, as announced in `initialize`
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            _ => None,
+        };
+        let Some(text) = text else {
+            return Vec::new();
+        };
+
+        let document = Document {
+            text: text.into(),
+            analysis: analyze(text),
+        };
+        let diagnostics = document
+            .analysis
+            .get_diagnostics()
+            .iter()
+            .map(|diagnostic| {
+                let mut message = diagnostic.get_message().clone();
+                for help in diagnostic.get_help() {
+                    message += &format!("\nhelp: {}", help);
+                }
+
+                json!({
+                    "range": to_range(text, diagnostic.get_span()),
+                    "severity": 1,
+                    "source": "toy-interpreter",
+                    "message": message,
+                })
+            })
+            .collect();
+
+        self.documents.insert(uri.into(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn document(&self, params: &Value) -> Option<&Document> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri)
+    }
+
+    fn find_binding(&self, params: &Value) -> Option<(&Document, &analysis::Binding)> {
+        let document = self.document(params)?;
+        let offset = to_offset(&document.text, &params["position"])?;
+
+        Some((document, document.analysis.find_binding(offset)?))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn to_document_symbol(text: &str, symbol: &Symbol) -> Value {
+    let kind = match symbol.get_kind() {
+        BindingKind::Function => 12,
+        BindingKind::Variable | BindingKind::Parameter => 13,
+    };
+    let children: Vec<Value> = symbol
+        .get_children()
+        .iter()
+        .map(|child| to_document_symbol(text, child))
+        .collect();
+
+    json!({
+        "name": symbol.get_name(),
+        "kind": kind,
+        "range": to_range(text, symbol.get_span()),
+        "selectionRange": to_range(text, symbol.get_selection_span()),
+        "children": children,
+    })
+}
+
+/// Positions count lines and UTF-16 code units, as that is the default of the protocol.
+fn to_position(text: &str, offset: usize) -> Value {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn to_range(text: &str, span: Span) -> Value {
+    json!({
+        "start": to_position(text, span.get_start()),
+        "end": to_position(text, span.get_end()),
+    })
+}
+
+fn to_offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+
+    let line_start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(line - 1)?.0 + 1
+    };
+
+    let mut units = 0;
+    for (i, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return Some(line_start + i);
+        }
+        units += ch.len_utf16();
+    }
+
+    Some(text.len())
+}
+
+/// Read one message, returns `None` at the end of the input.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(body))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Serve the language server protocol on stdin and stdout until the client asks to exit.
+pub fn start() -> ExitCode {
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+    let mut server = Server::default();
+
+    while !server.exit {
+        let body = match read_message(&mut reader) {
+            Ok(Some(body)) => body,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("error: could not read a message: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        for message in server.handle(&body) {
+            if let Err(e) = write_message(&mut writer, &message) {
+                eprintln!("error: could not write a message: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // exiting without a shutdown request is an error according to the protocol
+    if server.shutdown {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{read_message, to_offset, to_position, write_message, Server};
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": "file:///a.monkey", "languageId": "monkey", "version": 1, "text": text },
+            },
+        });
+        server.handle(message.to_string().as_bytes())
+    }
+
+    fn request(server: &mut Server, method: &str, line: u64, character: u64) -> Value {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": "file:///a.monkey" },
+                "position": { "line": line, "character": character },
+            },
+        });
+
+        let mut responses = server.handle(message.to_string().as_bytes());
+        assert_eq!(responses.len(), 1);
+        responses.remove(0)["result"].take()
+    }
+
+    #[test]
+    fn test_publish_diagnostics() {
+        let mut server = Server::default();
+
+        let messages = open(&mut server, "let x = 1;\nlet y = ;");
+        assert_eq!(messages[0]["method"], "textDocument/publishDiagnostics");
+
+        let diagnostics = messages[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": { "line": 1, "character": 8 }, "end": { "line": 1, "character": 9 } })
+        );
+
+        let messages = open(&mut server, "let x = 1;");
+        assert_eq!(messages[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "let add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nadd(1, 2)",
+        );
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        assert_eq!(symbols[0]["name"], "add");
+        assert_eq!(symbols[0]["kind"], 12);
+        assert_eq!(symbols[0]["children"][0]["name"], "sum");
+        assert_eq!(
+            symbols[0]["range"]["end"],
+            json!({ "line": 3, "character": 1 })
+        );
+
+        let definition = request(&mut server, "textDocument/definition", 4, 1);
+        assert_eq!(
+            definition["range"],
+            json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 7 } })
+        );
+
+        let hover = request(&mut server, "textDocument/hover", 2, 3);
+        assert_eq!(hover["contents"]["value"], "variable sum");
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 4, 4),
+            Value::Null
+        );
+
+        let completion = request(&mut server, "textDocument/completion", 0, 0);
+        assert!(completion
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|item| item["label"] == "return"));
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut server = Server::default();
+
+        let response =
+            server.handle(br#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#);
+        assert_eq!(response[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let response = server.handle(br#"{"jsonrpc":"2.0","id":2,"method":"unknown"}"#);
+        assert_eq!(response[0]["error"]["code"], -32601);
+
+        let response = server.handle(b"{not json");
+        assert_eq!(response[0]["error"]["code"], -32700);
+
+        let response = server.handle(br#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#);
+        assert_eq!(response[0]["result"], Value::Null);
+
+        for method in ["textDocument/completion", "shutdown", "initialize"] {
+            let message = json!({ "jsonrpc": "2.0", "id": 4, "method": method });
+            let response = server.handle(message.to_string().as_bytes());
+            assert_eq!(response[0]["id"], 4, "method: {}", method);
+            assert_eq!(response[0]["error"]["code"], -32600, "method: {}", method);
+        }
+
+        server.handle(br#"{"jsonrpc":"2.0","method":"exit"}"#);
+        assert!(server.shutdown && server.exit);
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "let s = \"äö𝄞\";\nx";
+
+        // `𝄞` takes four bytes, but only two UTF-16 code units
+        assert_eq!(to_position(text, 17), json!({ "line": 0, "character": 13 }));
+        assert_eq!(to_position(text, 21), json!({ "line": 1, "character": 1 }));
+        assert_eq!(
+            to_offset(text, &json!({ "line": 0, "character": 13 })),
+            Some(17)
+        );
+        assert_eq!(
+            to_offset(text, &json!({ "line": 1, "character": 0 })),
+            Some(20)
+        );
+        assert_eq!(
+            to_offset(text, &json!({ "line": 0, "character": 99 })),
+            Some(19)
+        );
+        assert_eq!(to_offset(text, &json!({ "line": 5, "character": 0 })), None);
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({ "id": 1 })).unwrap();
+        assert_eq!(output, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut input = output.as_slice();
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(b"{\"id\":1}".to_vec())
+        );
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+}
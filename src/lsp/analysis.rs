@@ -0,0 +1,501 @@
+use toy_interpreter::{
+    ast::{BlockStatement, Expression, Identifier, Statement},
+    diagnostics::Diagnostic,
+    Lexer, Parser, Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingKind {
+    Variable,
+    Function,
+    Parameter,
+}
+
+/// Name bound by a `let` statement or a function parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    name: String,
+    kind: BindingKind,
+    /// Span of the name where it is bound.
+    span: Span,
+    /// Parameters of the function, if the binding is a function.
+    parameters: Vec<String>,
+    scope: usize,
+    /// Offset from which on the binding shadows earlier ones with the same name.
+    visible_from: usize,
+}
+
+/// `let` binding as shown in the outline of the document, lets inside a function are children of
+/// the binding of the function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    name: String,
+    kind: BindingKind,
+    /// Span of the whole statement.
+    span: Span,
+    /// Span of the name.
+    selection_span: Span,
+    children: Vec<Symbol>,
+}
+
+/// Everything the language server knows about one version of a document.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    diagnostics: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+    bindings: Vec<Binding>,
+    /// Identifiers together with the binding they refer to, including the names of the bindings
+    /// themselves.
+    references: Vec<(Span, usize)>,
+}
+
+/// Collects the bindings of a program and the identifiers referring to them. Functions open a new
+/// scope, blocks of `if` expressions do not, just like in the evaluator.
+#[derive(Default)]
+struct Resolver {
+    bindings: Vec<Binding>,
+    /// Enclosing scope of each scope, the program is scope 0.
+    scope_parents: Vec<Option<usize>>,
+    scope: usize,
+    identifiers: Vec<(String, Span, usize)>,
+}
+
+/// Statements with syntax errors are left out, the rest of the document is still analyzed so that
+/// the outline and navigation keep working while typing.
+pub fn analyze(text: &str) -> Analysis {
+    let (program, errors) = Parser::new(Lexer::new(text.into())).parse_program_with_errors();
+
+    let mut symbols = Vec::new();
+    collect_symbols(program.get_statements(), &mut symbols);
+
+    let mut resolver = Resolver {
+        scope_parents: vec![None],
+        ..Default::default()
+    };
+    for statement in program.get_statements() {
+        resolver.statement(statement);
+    }
+
+    Analysis {
+        diagnostics: errors
+            .get_errors()
+            .iter()
+            .map(|e| e.to_diagnostic())
+            .collect(),
+        symbols,
+        references: resolver.resolve(),
+        bindings: resolver.bindings,
+    }
+}
+
+fn collect_symbols(statements: &[Statement], symbols: &mut Vec<Symbol>) {
+    for statement in statements {
+        match statement {
+            Statement::Let { name, value, .. } => {
+                let mut children = Vec::new();
+                collect_expression_symbols(value, &mut children);
+
+                symbols.push(Symbol {
+                    name: name.get_value().clone(),
+                    kind: binding_kind(value),
+                    span: statement.get_span(),
+                    selection_span: name.get_span(),
+                    children,
+                });
+            }
+            Statement::Return { value, .. } | Statement::Expression { value, .. } => {
+                collect_expression_symbols(value, symbols)
+            }
+        }
+    }
+}
+
+/// Lets inside of blocks of an expression belong to the enclosing statement.
+fn collect_expression_symbols(expression: &Expression, symbols: &mut Vec<Symbol>) {
+    match expression {
+        Expression::Identifier(_)
+        | Expression::Integer { .. }
+        | Expression::Float { .. }
+        | Expression::Boolean { .. }
+        | Expression::String { .. } => {}
+        Expression::Prefix { right, .. } => collect_expression_symbols(right, symbols),
+        Expression::Infix { left, right, .. }
+        | Expression::Index {
+            left, index: right, ..
+        } => {
+            collect_expression_symbols(left, symbols);
+            collect_expression_symbols(right, symbols);
+        }
+        Expression::Grouped { expression, .. } => collect_expression_symbols(expression, symbols),
+        Expression::If {
+            condition,
+            consequence,
+            alternative,
+            ..
+        } => {
+            collect_expression_symbols(condition, symbols);
+            collect_symbols(consequence.get_statements(), symbols);
+            if let Some(alternative) = alternative {
+                collect_symbols(alternative.get_statements(), symbols);
+            }
+        }
+        Expression::Function { body, .. } => collect_symbols(body.get_statements(), symbols),
+        Expression::Call {
+            function,
+            arguments: elements,
+            ..
+        } => {
+            collect_expression_symbols(function, symbols);
+            for element in elements {
+                collect_expression_symbols(element, symbols);
+            }
+        }
+        Expression::Array { elements, .. } => {
+            for element in elements {
+                collect_expression_symbols(element, symbols);
+            }
+        }
+        Expression::Hash { pairs, .. } => {
+            for (key, value) in pairs {
+                collect_expression_symbols(key, symbols);
+                collect_expression_symbols(value, symbols);
+            }
+        }
+    }
+}
+
+fn binding_kind(value: &Expression) -> BindingKind {
+    match value {
+        Expression::Function { .. } => BindingKind::Function,
+        Expression::Grouped { expression, .. } => binding_kind(expression),
+        _ => BindingKind::Variable,
+    }
+}
+
+fn function_parameters(value: &Expression) -> Vec<String> {
+    match value {
+        Expression::Function { parameters, .. } => {
+            parameters.iter().map(|p| p.get_value().clone()).collect()
+        }
+        Expression::Grouped { expression, .. } => function_parameters(expression),
+        _ => Vec::new(),
+    }
+}
+
+impl Resolver {
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { name, value, .. } => {
+                self.expression(value);
+
+                // a name only refers to the new binding after its statement, except for calls
+                // from function bodies that run later
+                self.bind(
+                    name,
+                    binding_kind(value),
+                    function_parameters(value),
+                    statement.get_span().get_end(),
+                );
+            }
+            Statement::Return { value, .. } | Statement::Expression { value, .. } => {
+                self.expression(value)
+            }
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) {
+        for statement in block.get_statements() {
+            self.statement(statement);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier(identifier) => self.identifiers.push((
+                identifier.get_value().clone(),
+                identifier.get_span(),
+                self.scope,
+            )),
+            Expression::Integer { .. }
+            | Expression::Float { .. }
+            | Expression::Boolean { .. }
+            | Expression::String { .. } => {}
+            Expression::Prefix { right, .. } => self.expression(right),
+            Expression::Infix { left, right, .. }
+            | Expression::Index {
+                left, index: right, ..
+            } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Grouped { expression, .. } => self.expression(expression),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                let outer = self.scope;
+                self.scope_parents.push(Some(outer));
+                self.scope = self.scope_parents.len() - 1;
+
+                for parameter in parameters {
+                    let start = parameter.get_span().get_start();
+                    self.bind(parameter, BindingKind::Parameter, Vec::new(), start);
+                }
+                self.block(body);
+
+                self.scope = outer;
+            }
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::Array { elements, .. } => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expression::Hash { pairs, .. } => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn bind(
+        &mut self,
+        name: &Identifier,
+        kind: BindingKind,
+        parameters: Vec<String>,
+        visible_from: usize,
+    ) {
+        self.bindings.push(Binding {
+            name: name.get_value().clone(),
+            kind,
+            span: name.get_span(),
+            parameters,
+            scope: self.scope,
+            visible_from,
+        });
+    }
+
+    /// Look up each identifier in its scope and the enclosing ones. The latest binding before
+    /// the identifier wins. Inside a function body it can also be the first one after it, as the
+    /// body only runs later on, while a name used before its `let` otherwise refers to an
+    /// enclosing scope like in the evaluator.
+    fn resolve(&self) -> Vec<(Span, usize)> {
+        let mut references: Vec<(Span, usize)> = (0..self.bindings.len())
+            .map(|i| (self.bindings[i].span, i))
+            .collect();
+
+        for (name, span, own_scope) in &self.identifiers {
+            let mut scope = Some(*own_scope);
+
+            while let Some(current) = scope {
+                let mut candidates = self
+                    .bindings
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| b.scope == current && &b.name == name);
+
+                let first = candidates.clone().next();
+                let before = candidates
+                    .by_ref()
+                    .filter(|(_, b)| b.visible_from <= span.get_start())
+                    .last();
+
+                let found = match current == *own_scope {
+                    true => before,
+                    false => before.or(first),
+                };
+                if let Some((index, _)) = found {
+                    references.push((*span, index));
+                    break;
+                }
+                scope = self.scope_parents[current];
+            }
+        }
+
+        references.sort_by_key(|(span, _)| span.get_start());
+        references
+    }
+}
+
+impl Analysis {
+    pub fn get_diagnostics(&self) -> &Vec<Diagnostic> {
+        &self.diagnostics
+    }
+
+    pub fn get_symbols(&self) -> &Vec<Symbol> {
+        &self.symbols
+    }
+
+    /// Binding of the identifier at the offset.
+    pub fn find_binding(&self, offset: usize) -> Option<&Binding> {
+        self.references
+            .iter()
+            .find(|(span, _)| span.get_start() <= offset && offset <= span.get_end())
+            .map(|(_, index)| &self.bindings[*index])
+    }
+}
+
+impl Binding {
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    /// Short description of the binding shown on hover, e.g. `function add(a, b)`.
+    pub fn describe(&self) -> String {
+        match self.kind {
+            BindingKind::Variable => format!("variable {}", self.name),
+            BindingKind::Function => {
+                format!("function {}({})", self.name, self.parameters.join(", "))
+            }
+            BindingKind::Parameter => format!("parameter {}", self.name),
+        }
+    }
+}
+
+impl Symbol {
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_kind(&self) -> BindingKind {
+        self.kind
+    }
+
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    pub fn get_selection_span(&self) -> Span {
+        self.selection_span
+    }
+
+    pub fn get_children(&self) -> &Vec<Symbol> {
+        &self.children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, BindingKind};
+    use toy_interpreter::Span;
+
+    #[test]
+    fn test_diagnostics() {
+        let analysis = analyze("let x = ;\nlet = 5;");
+
+        let messages: Vec<&String> = analysis
+            .get_diagnostics()
+            .iter()
+            .map(|d| d.get_message())
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(analysis.get_symbols().is_empty());
+        assert!(analyze("let x = 5;").get_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_symbols() {
+        let analysis =
+            analyze("let add = fn(a, b) { let sum = a + b; sum };\nif (true) { let y = 1; }");
+        let symbols = analysis.get_symbols();
+
+        let outline: Vec<(&str, BindingKind, usize)> = symbols
+            .iter()
+            .map(|s| (s.get_name().as_str(), s.get_kind(), s.get_children().len()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                ("add", BindingKind::Function, 1),
+                ("y", BindingKind::Variable, 0)
+            ]
+        );
+
+        assert_eq!(symbols[0].get_span(), Span::new(0, 43));
+        assert_eq!(symbols[0].get_selection_span(), Span::new(4, 7));
+        assert_eq!(symbols[0].get_children()[0].get_name(), "sum");
+    }
+
+    #[test]
+    fn test_find_binding() {
+        let input = "let x = 1;\nlet f = fn(x) { x + y };\nlet y = x;\nlet x = x + 1;\nx";
+        let analysis = analyze(input);
+
+        let definition = |offset| analysis.find_binding(offset).map(|b| b.get_span());
+        let tests = [
+            // the parameter shadows the global
+            (27, Some(Span::new(22, 23))),
+            // `y` is bound after the function
+            (31, Some(Span::new(40, 41))),
+            // the value of a let still refers to the previous binding
+            (44, Some(Span::new(4, 5))),
+            (55, Some(Span::new(4, 5))),
+            (62, Some(Span::new(51, 52))),
+            // the name of a binding refers to itself
+            (51, Some(Span::new(51, 52))),
+            (8, None),
+        ];
+
+        for (offset, expected) in tests {
+            assert_eq!(definition(offset), expected, "offset: {}", offset);
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let analysis = analyze("let add = fn(a, b) { a + b }; let x = add(1, 2);");
+
+        let describe = |offset| analysis.find_binding(offset).unwrap().describe();
+        assert_eq!(describe(4), "function add(a, b)");
+        assert_eq!(describe(21), "parameter a");
+        assert_eq!(describe(34), "variable x");
+    }
+
+    #[test]
+    fn test_later_bindings_only_inside_functions() {
+        let analysis = analyze("x; let x = 1; let f = fn() { y }; let y = 2;");
+
+        let definition = |offset| analysis.find_binding(offset).map(|b| b.get_span());
+        assert_eq!(definition(0), None);
+        assert_eq!(definition(29), Some(Span::new(38, 39)));
+    }
+
+    #[test]
+    fn test_analysis_with_syntax_errors() {
+        let analysis = analyze("let x = 1;\nlet = 2;\nlet y = x;");
+
+        assert_eq!(analysis.get_diagnostics().len(), 1);
+        let names: Vec<&String> = analysis
+            .get_symbols()
+            .iter()
+            .map(|s| s.get_name())
+            .collect();
+        assert_eq!(names, vec!["x", "y"]);
+        assert_eq!(
+            analysis.find_binding(28).map(|b| b.get_span()),
+            Some(Span::new(4, 5))
+        );
+    }
+}
@@ -1,12 +1,64 @@
-mod ast;
-mod lexer;
-mod parser;
+mod lsp;
 mod repl;
-mod token;
+mod runner;
 
-fn main() {
-    println!("Hello to the this toy interpreter!");
-    println!("Feel free to type in commands");
+use std::process::ExitCode;
 
-    repl::start();
+const USAGE: &str = "usage:
+    toy-interpreter                 start the repl
+    toy-interpreter run <file>      run a script, use `-` to read it from stdin
+    toy-interpreter run --vm <file> compile the script to bytecode and run it on the vm
+    toy-interpreter compile <file> [-o <output>]
+                                    compile a script to a bytecode file, `<file>.tbc` by default
+    toy-interpreter disassemble <file>
+                                    print the bytecode of a script or a bytecode file
+    toy-interpreter fmt [--check] <file>...
+                                    format scripts in place, `--check` only reports the scripts
+                                    that are not formatted
+    toy-interpreter lsp             start a language server on stdin and stdout
+
+bytecode files are run on the vm without parsing the source, like `run script.tbc`";
+
+/// The evaluator recurses for every nested call, the main thread of debug builds would not have
+/// enough stack for the deepest calls that are allowed.
+const STACK_SIZE: usize = 64 << 20;
+
+fn main() -> ExitCode {
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("could not start the main thread")
+        .join()
+        .unwrap_or(ExitCode::FAILURE)
+}
+
+fn run() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args[..] {
+        [] => {
+            println!("Hello to the this toy interpreter!");
+            println!("Feel free to type in commands");
+
+            repl::start();
+            ExitCode::SUCCESS
+        }
+        ["run", path] => runner::run(path, runner::Engine::Evaluator),
+        ["run", "--vm", path] => runner::run(path, runner::Engine::Vm),
+        ["compile", path] => runner::compile(path, None),
+        ["compile", path, "-o", output] => runner::compile(path, Some(output)),
+        ["disassemble", path] => runner::disassemble(path),
+        ["fmt", "--check", ref paths @ ..] if !paths.is_empty() => runner::format(paths, true),
+        ["fmt", ref paths @ ..] if !paths.is_empty() => runner::format(paths, false),
+        ["lsp"] => lsp::start(),
+        ["help" | "-h" | "--help"] => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
 }
@@ -1,29 +1,45 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use crate::{
-    ast::{Identifier, Program, Statement},
+    ast::{BlockStatement, Expression, Identifier, Program, Statement},
+    diagnostics::Diagnostic,
     lexer::Lexer,
-    token::{Token, TokenType},
+    token::{Number, Token, TokenType},
 };
 
-#[derive(Debug)]
+/// Errors after this many are most likely follow-up errors of earlier ones and are dropped.
+const MAX_ERRORS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParserError {
     message: String,
     token: Token,
 }
 
-#[derive(Debug)]
+impl ParserError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.message.clone(), self.token.get_span());
+
+        match self.token.get_type() {
+            TokenType::Eof => diagnostic
+                .with_help("the input ended too early, check for unclosed delimiters".into()),
+            TokenType::Illegal if self.token.get_literal() == "unterminated string literal" => {
+                diagnostic.with_help("add a closing `\"` to end the string".into())
+            }
+            TokenType::Illegal if self.token.get_literal() == "unterminated block comment" => {
+                diagnostic.with_help("add a closing `*/` to end the comment".into())
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct ParserErrorList {
     errors: Vec<ParserError>,
 }
 
 impl ParserErrorList {
-    pub fn new() -> ParserErrorList {
-        ParserErrorList {
-            errors: Vec::<ParserError>::new(),
-        }
-    }
-
     pub fn add_error(&mut self, error: ParserError) {
         self.errors.push(error);
     }
@@ -36,18 +52,63 @@ impl ParserErrorList {
 impl fmt::Display for ParserErrorList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for e in self.get_errors() {
-            writeln!(f, "msg: {}, token: {:?}", e.message, e.token)?
+            let span = e.token.get_span();
+            writeln!(
+                f,
+                "error: {} (at {}..{})",
+                e.message,
+                span.get_start(),
+                span.get_end()
+            )?
         }
 
         Ok(())
     }
 }
 
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub(crate) enum Precedence {
+    Lowest,
+    Or,
+    And,
+    Equals,
+    LessGreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+    Index,
+}
+
+impl Precedence {
+    pub(crate) fn from_token_type(token_type: &TokenType) -> Precedence {
+        match token_type {
+            TokenType::Eq | TokenType::NotEq => Precedence::Equals,
+            TokenType::Or => Precedence::Or,
+            TokenType::And => Precedence::And,
+            TokenType::Lt | TokenType::Gt | TokenType::LtEq | TokenType::GtEq => {
+                Precedence::LessGreater
+            }
+            TokenType::Plus | TokenType::Minus => Precedence::Sum,
+            TokenType::Asterisk | TokenType::Slash | TokenType::Percent => Precedence::Product,
+            TokenType::Lparen => Precedence::Call,
+            TokenType::Lbracket => Precedence::Index,
+            _ => Precedence::Lowest,
+        }
+    }
+}
+
+type PrefixParseFn = fn(&mut Parser) -> Result<Expression, ParserError>;
+type InfixParseFn = fn(&mut Parser, Expression) -> Result<Expression, ParserError>;
+
 #[derive(Debug, Default, Clone)]
 pub struct Parser {
     lexer: Lexer,
     current_token: Token,
     next_token: Token,
+    prefix_parse_fns: HashMap<TokenType, PrefixParseFn>,
+    infix_parse_fns: HashMap<TokenType, InfixParseFn>,
+    errors: ParserErrorList,
 }
 
 impl Parser {
@@ -57,6 +118,42 @@ impl Parser {
             ..Default::default()
         };
 
+        parser.register_prefix(TokenType::Ident, Parser::parse_identifier);
+        parser.register_prefix(TokenType::Int, Parser::parse_integer);
+        parser.register_prefix(TokenType::Float, Parser::parse_float);
+        parser.register_prefix(TokenType::String, Parser::parse_string);
+        parser.register_prefix(TokenType::True, Parser::parse_boolean);
+        parser.register_prefix(TokenType::False, Parser::parse_boolean);
+        parser.register_prefix(TokenType::Bang, Parser::parse_prefix_expression);
+        parser.register_prefix(TokenType::Minus, Parser::parse_prefix_expression);
+        parser.register_prefix(TokenType::Lparen, Parser::parse_grouped_expression);
+        parser.register_prefix(TokenType::If, Parser::parse_if_expression);
+        parser.register_prefix(TokenType::Function, Parser::parse_function_literal);
+        parser.register_prefix(TokenType::Lbracket, Parser::parse_array_literal);
+        // blocks are only parsed after `if`, `else` and `fn`, a brace anywhere else starts a hash
+        parser.register_prefix(TokenType::Lbrace, Parser::parse_hash_literal);
+        parser.register_prefix(TokenType::Illegal, Parser::parse_illegal);
+
+        for token_type in [
+            TokenType::Plus,
+            TokenType::Minus,
+            TokenType::Asterisk,
+            TokenType::Slash,
+            TokenType::Percent,
+            TokenType::Lt,
+            TokenType::Gt,
+            TokenType::LtEq,
+            TokenType::GtEq,
+            TokenType::Eq,
+            TokenType::NotEq,
+            TokenType::And,
+            TokenType::Or,
+        ] {
+            parser.register_infix(token_type, Parser::parse_infix_expression);
+        }
+        parser.register_infix(TokenType::Lparen, Parser::parse_call_expression);
+        parser.register_infix(TokenType::Lbracket, Parser::parse_index_expression);
+
         // fill the current and next token with real values
         parser.next_token();
         parser.next_token();
@@ -64,6 +161,14 @@ impl Parser {
         parser
     }
 
+    fn register_prefix(&mut self, token_type: TokenType, function: PrefixParseFn) {
+        self.prefix_parse_fns.insert(token_type, function);
+    }
+
+    fn register_infix(&mut self, token_type: TokenType, function: InfixParseFn) {
+        self.infix_parse_fns.insert(token_type, function);
+    }
+
     fn next_token(&mut self) {
         self.current_token = self.next_token.clone();
         self.next_token = self.lexer.next_token();
@@ -85,14 +190,19 @@ impl Parser {
         }
     }
 
+    fn current_precedence(&self) -> Precedence {
+        Precedence::from_token_type(&self.current_token.get_type())
+    }
+
+    fn next_precedence(&self) -> Precedence {
+        Precedence::from_token_type(&self.next_token.get_type())
+    }
+
     fn parse_statement(&mut self) -> Result<Statement, ParserError> {
         match self.current_token.get_type() {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
-            n => Err(ParserError {
-                message: format!("Unknown token type for statement: {:?}", n),
-                token: self.current_token.clone(),
-            }),
+            _ => self.parse_expression_statement(),
         }
     }
 
@@ -107,51 +217,404 @@ impl Parser {
         );
 
         self.expect_next_token(TokenType::Assign)?;
+        self.next_token();
 
-        // for now, just skip the expression
-        loop {
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.next_token.get_type() == TokenType::Semicolon {
             self.next_token();
-            if self.current_token.get_type() == TokenType::Semicolon {
-                break;
-            }
         }
 
         Ok(Statement::Let {
             token,
             name: identifier,
+            value,
         })
     }
 
     fn parse_return_statement(&mut self) -> Result<Statement, ParserError> {
         let token = self.current_token.clone();
+        self.next_token();
 
-        // for now, just skip the expression
-        loop {
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.next_token.get_type() == TokenType::Semicolon {
+            self.next_token();
+        }
+
+        Ok(Statement::Return { token, value })
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<Statement, ParserError> {
+        let token = self.current_token.clone();
+
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.next_token.get_type() == TokenType::Semicolon {
+            self.next_token();
+        }
+
+        Ok(Statement::Expression { token, value })
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParserError> {
+        let prefix = match self.prefix_parse_fns.get(&self.current_token.get_type()) {
+            Some(prefix) => *prefix,
+            None => {
+                return Err(ParserError {
+                    message: format!(
+                        "No prefix parse function for token type: {:?}",
+                        self.current_token.get_type()
+                    ),
+                    token: self.current_token.clone(),
+                })
+            }
+        };
+
+        let mut left = prefix(self)?;
+
+        // bind tighter operators to the left side until a weaker one (or the end) is reached
+        while self.next_token.get_type() != TokenType::Semicolon
+            && precedence < self.next_precedence()
+        {
+            let infix = match self.infix_parse_fns.get(&self.next_token.get_type()) {
+                Some(infix) => *infix,
+                None => return Ok(left),
+            };
+
+            self.next_token();
+            left = infix(self, left)?;
+        }
+
+        Ok(left)
+    }
+
+    fn parse_identifier(&mut self) -> Result<Expression, ParserError> {
+        Ok(Expression::Identifier(Identifier::new(
+            self.current_token.clone(),
+            self.current_token.get_literal(),
+        )))
+    }
+
+    fn parse_integer(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        match token.get_number() {
+            Some(Number::Int(value)) => Ok(Expression::Integer { token, value }),
+            _ => Err(ParserError {
+                message: format!("Could not parse \"{}\" as integer", token.get_literal()),
+                token,
+            }),
+        }
+    }
+
+    fn parse_float(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        match token.get_number() {
+            Some(Number::Float(value)) => Ok(Expression::Float { token, value }),
+            _ => Err(ParserError {
+                message: format!("Could not parse \"{}\" as float", token.get_literal()),
+                token,
+            }),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<Expression, ParserError> {
+        Ok(Expression::String {
+            token: self.current_token.clone(),
+            value: self.current_token.get_literal(),
+        })
+    }
+
+    fn parse_boolean(&mut self) -> Result<Expression, ParserError> {
+        Ok(Expression::Boolean {
+            token: self.current_token.clone(),
+            value: self.current_token.get_type() == TokenType::True,
+        })
+    }
+
+    fn parse_prefix_expression(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        self.next_token();
+
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Ok(Expression::Prefix {
+            operator: token.get_literal(),
+            token,
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        let precedence = self.current_precedence();
+        self.next_token();
+
+        let right = self.parse_expression(precedence)?;
+
+        Ok(Expression::Infix {
+            operator: token.get_literal(),
+            token,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+
+        self.expect_next_token(TokenType::Lparen)?;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        self.expect_next_token(TokenType::Rparen)?;
+
+        self.expect_next_token(TokenType::Lbrace)?;
+        let consequence = self.parse_block_statement()?;
+
+        let alternative = if self.next_token.get_type() == TokenType::Else {
+            self.next_token();
+            self.expect_next_token(TokenType::Lbrace)?;
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+
+        Ok(Expression::If {
+            token,
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        })
+    }
+
+    fn parse_block_statement(&mut self) -> Result<BlockStatement, ParserError> {
+        let token = self.current_token.clone();
+        let mut statements = Vec::new();
+        self.next_token();
+
+        while self.current_token.get_type() != TokenType::Rbrace {
+            if self.current_token.get_type() == TokenType::Eof {
+                return Err(ParserError {
+                    message: "Expected \"Rbrace\" to close the block, but got \"Eof\"".into(),
+                    token: self.current_token.clone(),
+                });
+            }
+
+            match self.parse_statement() {
+                Ok(statement) => {
+                    statements.push(statement);
+                    self.next_token();
+                }
+                Err(e) => {
+                    self.record_error(e);
+                    self.synchronize();
+                }
+            }
+        }
+
+        let span = token.get_span().merge(self.current_token.get_span());
+        Ok(BlockStatement::new(token, statements, span))
+    }
+
+    fn parse_function_literal(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+
+        self.expect_next_token(TokenType::Lparen)?;
+        let parameters = self.parse_function_parameters()?;
+
+        self.expect_next_token(TokenType::Lbrace)?;
+        let body = self.parse_block_statement()?;
+
+        Ok(Expression::Function {
+            token,
+            parameters,
+            body,
+        })
+    }
+
+    fn parse_function_parameters(&mut self) -> Result<Vec<Identifier>, ParserError> {
+        let mut parameters = Vec::new();
+
+        if self.next_token.get_type() == TokenType::Rparen {
             self.next_token();
-            if self.current_token.get_type() == TokenType::Semicolon {
+            return Ok(parameters);
+        }
+
+        loop {
+            self.expect_next_token(TokenType::Ident)?;
+            parameters.push(Identifier::new(
+                self.current_token.clone(),
+                self.current_token.get_literal(),
+            ));
+
+            if self.next_token.get_type() != TokenType::Comma {
                 break;
             }
+            self.next_token();
         }
 
-        Ok(Statement::Return { token })
+        self.expect_next_token(TokenType::Rparen)?;
+
+        Ok(parameters)
     }
 
-    pub fn parse_program(mut self) -> Result<Program, ParserErrorList> {
-        let mut program = Program::new();
-        let mut errors = ParserErrorList::new();
+    fn parse_call_expression(&mut self, function: Expression) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        let arguments = self.parse_expression_list(TokenType::Rparen)?;
+
+        // the closing parenthesis is the current token after parsing the arguments
+        let span = function.get_span().merge(self.current_token.get_span());
+
+        Ok(Expression::Call {
+            token,
+            function: Box::new(function),
+            arguments,
+            span,
+        })
+    }
+
+    fn parse_array_literal(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        let elements = self.parse_expression_list(TokenType::Rbracket)?;
+
+        Ok(Expression::Array {
+            span: token.get_span().merge(self.current_token.get_span()),
+            token,
+            elements,
+        })
+    }
+
+    fn parse_hash_literal(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        let mut pairs = Vec::new();
+
+        while self.next_token.get_type() != TokenType::Rbrace {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            self.expect_next_token(TokenType::Colon)?;
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+
+            pairs.push((key, value));
+
+            if self.next_token.get_type() != TokenType::Rbrace {
+                self.expect_next_token(TokenType::Comma)?;
+            }
+        }
+
+        self.expect_next_token(TokenType::Rbrace)?;
+
+        Ok(Expression::Hash {
+            span: token.get_span().merge(self.current_token.get_span()),
+            token,
+            pairs,
+        })
+    }
+
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        self.next_token();
+
+        let index = self.parse_expression(Precedence::Lowest)?;
+        self.expect_next_token(TokenType::Rbracket)?;
+
+        Ok(Expression::Index {
+            span: left.get_span().merge(self.current_token.get_span()),
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+        })
+    }
+
+    /// Parse comma separated expressions until the `end` token, starting on the opening token.
+    fn parse_expression_list(&mut self, end: TokenType) -> Result<Vec<Expression>, ParserError> {
+        let mut list = Vec::new();
+
+        if self.next_token.get_type() == end {
+            self.next_token();
+            return Ok(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.next_token.get_type() == TokenType::Comma {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        self.expect_next_token(end)?;
+
+        Ok(list)
+    }
+
+    fn parse_illegal(&mut self) -> Result<Expression, ParserError> {
+        // the lexer describes the problem in the literal of illegal tokens
+        Err(ParserError {
+            message: format!("Illegal token: {}", self.current_token.get_literal()),
+            token: self.current_token.clone(),
+        })
+    }
+
+    fn parse_grouped_expression(&mut self) -> Result<Expression, ParserError> {
+        let token = self.current_token.clone();
+        self.next_token();
+
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        self.expect_next_token(TokenType::Rparen)?;
+
+        Ok(Expression::Grouped {
+            span: token.get_span().merge(self.current_token.get_span()),
+            token,
+            expression: Box::new(expression),
+        })
+    }
+
+    /// Keep an error, unless it reports the same problem as the previous one or there are already
+    /// too many errors to be helpful.
+    fn record_error(&mut self, error: ParserError) {
+        let errors = self.errors.get_errors();
+        if errors.len() >= MAX_ERRORS {
+            return;
+        }
+
+        if let Some(last) = errors.last() {
+            if last.token.get_span() == error.token.get_span() && last.message == error.message {
+                return;
+            }
+        }
 
+        self.errors.add_error(error);
+    }
+
+    /// Skip the rest of a broken statement, stopping after the next `;`, in front of the next `}`
+    /// or statement keyword, or at the end of the input.
+    fn synchronize(&mut self) {
         loop {
-            let statement = self.parse_statement();
-            match statement {
-                Ok(statement) => program.add_statement(statement),
-                Err(e) => errors.add_error(e),
+            match self.current_token.get_type() {
+                TokenType::Eof | TokenType::Rbrace => return,
+                TokenType::Semicolon => {
+                    self.next_token();
+                    return;
+                }
+                _ => {}
             }
 
             self.next_token();
-            if self.current_token.get_type() == TokenType::Eof {
-                break;
+
+            if matches!(
+                self.current_token.get_type(),
+                TokenType::Let | TokenType::Return
+            ) {
+                return;
             }
         }
+    }
+
+    pub fn parse_program(self) -> Result<Program, ParserErrorList> {
+        let (program, errors) = self.parse_program_with_errors();
 
         if errors.get_errors().is_empty() {
             Ok(program)
@@ -159,12 +622,164 @@ impl Parser {
             Err(errors)
         }
     }
+
+    /// Like [`Parser::parse_program`], but the statements that could be parsed are kept next to
+    /// the errors, e.g. for an editor that still wants to work with the rest of a broken file.
+    pub fn parse_program_with_errors(mut self) -> (Program, ParserErrorList) {
+        let mut program = Program::new();
+
+        while self.current_token.get_type() != TokenType::Eof
+            && self.errors.get_errors().len() < MAX_ERRORS
+        {
+            match self.parse_statement() {
+                Ok(statement) => {
+                    program.add_statement(statement);
+                    self.next_token();
+                }
+                Err(e) => {
+                    self.record_error(e);
+                    self.synchronize();
+
+                    // a closing brace on the top level most likely belonged to the broken
+                    // statement (as well as a semicolon after it), reporting them again would
+                    // only add noise
+                    if self.current_token.get_type() == TokenType::Rbrace {
+                        self.next_token();
+
+                        if self.current_token.get_type() == TokenType::Semicolon {
+                            self.next_token();
+                        }
+                    }
+                }
+            }
+        }
+
+        (program, self.errors)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Parser;
-    use crate::{ast::Statement, lexer::Lexer};
+    use crate::{
+        ast::{BlockStatement, Expression, Program, Statement},
+        lexer::Lexer,
+        span::Span,
+    };
+
+    fn parse(input: &str) -> Program {
+        let lexer = Lexer::new(input.into());
+        let parser = Parser::new(lexer);
+        let program = parser.parse_program();
+
+        if let Err(errors) = program {
+            println!("{}", errors);
+            panic!("Program could not be parsed correctly");
+        }
+
+        program.unwrap()
+    }
+
+    fn parenthesize(expression: &Expression) -> String {
+        match expression {
+            Expression::Identifier(identifier) => identifier.get_value().clone(),
+            Expression::Integer { value, .. } => value.to_string(),
+            Expression::Float { value, .. } => value.to_string(),
+            Expression::Boolean { value, .. } => value.to_string(),
+            Expression::String { value, .. } => format!("{:?}", value),
+            Expression::Prefix {
+                operator, right, ..
+            } => format!("({}{})", operator, parenthesize(right)),
+            Expression::Infix {
+                left,
+                operator,
+                right,
+                ..
+            } => format!(
+                "({} {} {})",
+                parenthesize(left),
+                operator,
+                parenthesize(right)
+            ),
+            Expression::Grouped { expression, .. } => parenthesize(expression),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+                ..
+            } => match alternative {
+                Some(alternative) => format!(
+                    "if {} {{ {} }} else {{ {} }}",
+                    parenthesize(condition),
+                    parenthesize_block(consequence),
+                    parenthesize_block(alternative)
+                ),
+                None => format!(
+                    "if {} {{ {} }}",
+                    parenthesize(condition),
+                    parenthesize_block(consequence)
+                ),
+            },
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                let parameters: Vec<&str> =
+                    parameters.iter().map(|p| p.get_value().as_str()).collect();
+
+                format!(
+                    "fn({}) {{ {} }}",
+                    parameters.join(", "),
+                    parenthesize_block(body)
+                )
+            }
+            Expression::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(parenthesize).collect();
+                format!("{}({})", parenthesize(function), arguments.join(", "))
+            }
+            Expression::Array { elements, .. } => {
+                let elements: Vec<String> = elements.iter().map(parenthesize).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expression::Index { left, index, .. } => {
+                format!("({}[{}])", parenthesize(left), parenthesize(index))
+            }
+            Expression::Hash { pairs, .. } => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", parenthesize(key), parenthesize(value)))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+
+    fn parenthesize_block(block: &BlockStatement) -> String {
+        let statements: Vec<String> = block
+            .get_statements()
+            .iter()
+            .map(|s| match s {
+                Statement::Expression { value, .. } => parenthesize(value),
+                s => panic!("Expected expression statement, got {:?}", s),
+            })
+            .collect();
+
+        statements.join("; ")
+    }
+
+    fn return_values(program: &Program) -> Vec<String> {
+        program
+            .get_statements()
+            .iter()
+            .map(|s| match s {
+                Statement::Return { value, .. } => parenthesize(value),
+                s => panic!("Expected return statement, got {:?}", s),
+            })
+            .collect()
+    }
 
     #[test]
     fn test_return_statement() {
@@ -223,4 +838,487 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_let_statement_values() {
+        let program = parse("let x = 5; let y = true; let z = y;");
+
+        let expected = [("x", "5"), ("y", "true"), ("z", "y")];
+        for (i, s) in program.get_statements().iter().enumerate() {
+            match s {
+                Statement::Let { name, value, .. } => {
+                    assert_eq!(expected[i].0, name.get_value());
+                    assert_eq!(expected[i].1, parenthesize(value));
+                }
+                s => panic!("Expected let statement, got {:?}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_literal_expressions() {
+        let program = parse("return foobar; return 5; return 3.14; return true; return false;");
+
+        assert_eq!(
+            return_values(&program),
+            vec!["foobar", "5", "3.14", "true", "false"]
+        );
+    }
+
+    #[test]
+    fn test_prefix_expressions() {
+        let program = parse("return !5; return -15; return !true; return -3.5;");
+
+        assert_eq!(
+            return_values(&program),
+            vec!["(!5)", "(-15)", "(!true)", "(-3.5)"]
+        );
+    }
+
+    #[test]
+    fn test_infix_expressions() {
+        let operators = ["+", "-", "*", "/", ">", "<", "==", "!="];
+
+        for operator in operators {
+            let program = parse(&format!("return 5 {} 5;", operator));
+            assert_eq!(return_values(&program), vec![format!("(5 {} 5)", operator)]);
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let tests = [
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b + c", "((a + b) + c)"),
+            ("a + b - c", "((a + b) - c)"),
+            ("a * b * c", "((a * b) * c)"),
+            ("a * b / c", "((a * b) / c)"),
+            ("a + b % c * d", "(a + ((b % c) * d))"),
+            ("a + b / c", "(a + (b / c))"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
+            (
+                "3 + 4 * 5 == 3 * 1 + 4 * 5",
+                "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
+            ),
+            ("3 > 5 == false", "((3 > 5) == false)"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4)"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("2 / (5 + 5)", "(2 / (5 + 5))"),
+            ("-(5 + 5)", "(-(5 + 5))"),
+            ("!(true == true)", "(!(true == true))"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))",
+            ),
+            (
+                "add(a + b + c * d / f + g)",
+                "add((((a + b) + ((c * d) / f)) + g))",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("-a[1]", "(-(a[1]))"),
+            ("f(x)[1]", "(f(x)[1])"),
+            ("a <= b == b >= a", "((a <= b) == (b >= a))"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c && d", "((a && b) || (c && d))"),
+            ("a == b && c != d", "((a == b) && (c != d))"),
+            ("a || b || c", "((a || b) || c)"),
+            ("!a && b", "((!a) && b)"),
+            ("a < 1 + 2 || b", "((a < (1 + 2)) || b)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(&format!("return {};", input));
+            assert_eq!(return_values(&program), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_index_with_zero_literal() {
+        let program = parse("return f(x)[0]; return [0][0x0];");
+
+        assert_eq!(return_values(&program), vec!["(f(x)[0])", "([0][0])"]);
+    }
+
+    #[test]
+    fn test_string_expression() {
+        let program = parse("return \"hello world\";");
+
+        assert_eq!(return_values(&program), vec!["\"hello world\""]);
+    }
+
+    #[test]
+    fn test_illegal_token() {
+        let lexer = Lexer::new("let x = \"foo;".into());
+        let errors = Parser::new(lexer).parse_program().unwrap_err();
+
+        assert_eq!(
+            errors.get_errors()[0].message,
+            "Illegal token: unterminated string literal"
+        );
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let tests = [
+            ("if (x < y) { x }", "if (x < y) { x }"),
+            ("if (x < y) { x } else { y }", "if (x < y) { x } else { y }"),
+            (
+                "if (x) { x; y } else { if (y) { y } }",
+                "if x { x; y } else { if y { y } }",
+            ),
+            ("let z = if (x) { 1 } else { 2 };", "if x { 1 } else { 2 }"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            match &program.get_statements()[..] {
+                [Statement::Expression { value, .. }] | [Statement::Let { value, .. }] => {
+                    assert_eq!(parenthesize(value), expected, "input: {}", input)
+                }
+                s => panic!("Expected one statement, got {:?}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_if_expressions() {
+        let tests = [
+            "if x { x }",
+            "if (x { x }",
+            "if (x) x",
+            "if (x) { x } else x",
+        ];
+
+        for input in tests {
+            let lexer = Lexer::new(input.into());
+            let parser = Parser::new(lexer);
+
+            assert!(parser.parse_program().is_err(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_literal() {
+        let tests = [
+            ("fn() {};", "fn() {  }"),
+            ("fn(x) { x };", "fn(x) { x }"),
+            ("fn(x, y) { x + y; };", "fn(x, y) { (x + y) }"),
+            ("fn(x, y, z) { x; y * z };", "fn(x, y, z) { x; (y * z) }"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            match &program.get_statements()[..] {
+                [Statement::Expression { value, .. }] => {
+                    assert_eq!(parenthesize(value), expected, "input: {}", input)
+                }
+                s => panic!("Expected one expression statement, got {:?}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_expression() {
+        let program = parse("let result = add(1, 2 * 3, fn(x) { x }(4));");
+
+        match &program.get_statements()[..] {
+            [Statement::Let { value, .. }] => {
+                assert_eq!(parenthesize(value), "add(1, (2 * 3), fn(x) { x }(4))")
+            }
+            s => panic!("Expected one let statement, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn test_array_and_index_expressions() {
+        let program = parse("[]; [1, 2 * 2, \"x\"]; arr[1 + 1]; [[1], [2]][1][1]");
+
+        let expressions: Vec<String> = program
+            .get_statements()
+            .iter()
+            .map(|s| match s {
+                Statement::Expression { value, .. } => parenthesize(value),
+                s => panic!("Expected expression statement, got {:?}", s),
+            })
+            .collect();
+
+        assert_eq!(
+            expressions,
+            vec![
+                "[]",
+                "[1, (2 * 2), \"x\"]",
+                "(arr[(1 + 1)])",
+                "(([[1], [2]][1])[1])"
+            ]
+        );
+
+        let spans: Vec<Span> = program
+            .get_statements()
+            .iter()
+            .map(|s| s.get_span())
+            .collect();
+        assert_eq!(spans[1], Span::new(4, 19));
+        assert_eq!(spans[2], Span::new(21, 31));
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let tests = [
+            ("{}", "{}"),
+            ("{\"one\": 1, \"two\": 2}", "{\"one\": 1, \"two\": 2}"),
+            ("{1: true, false: \"no\",}", "{1: true, false: \"no\"}"),
+            (
+                "{\"sum\": 1 + 2, a * b: [1]}",
+                "{\"sum\": (1 + 2), (a * b): [1]}",
+            ),
+            (
+                "{\"inner\": {\"x\": 1}}[\"inner\"]",
+                "({\"inner\": {\"x\": 1}}[\"inner\"])",
+            ),
+            ("if (x) { {1: 2} }", "if x { {1: 2} }"),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            match &program.get_statements()[0] {
+                Statement::Expression { value, .. } => {
+                    assert_eq!(parenthesize(value), expected, "input: {}", input)
+                }
+                s => panic!("Expected expression statement, got {:?}", s),
+            }
+        }
+
+        let program = parse("let h = {1: 2};");
+        match &program.get_statements()[0] {
+            Statement::Let { value, .. } => assert_eq!(value.get_span(), Span::new(8, 14)),
+            s => panic!("Expected let statement, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn test_invalid_hash_literals() {
+        let tests = [
+            (
+                "{1}",
+                "Expected next token to be of type \"Colon\", but got \"Rbrace\"",
+            ),
+            ("{1: }", "No prefix parse function for token type: Rbrace"),
+            (
+                "{1: 2 3: 4}",
+                "Expected next token to be of type \"Comma\", but got \"Int\"",
+            ),
+            (
+                "{1: 2",
+                "Expected next token to be of type \"Comma\", but got \"Eof\"",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse_errors(input)[0], expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_function_literals() {
+        let tests = [
+            "fn(x, ) { x }",
+            "fn(1) { x }",
+            "fn(x) { x",
+            "add(1, 2",
+            "[1, 2",
+            "a[1",
+            "a[]",
+        ];
+
+        for input in tests {
+            let lexer = Lexer::new(input.into());
+            let parser = Parser::new(lexer);
+
+            assert!(parser.parse_program().is_err(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let input = "let x = -a * (b + c);\nadd(1, fn(y) { y })\nif (x) { 1 } else { 2 }";
+        let program = parse(input);
+
+        let spans: Vec<Span> = program
+            .get_statements()
+            .iter()
+            .map(|s| s.get_span())
+            .collect();
+        assert_eq!(
+            spans,
+            vec![Span::new(0, 20), Span::new(22, 41), Span::new(42, 65)]
+        );
+
+        match &program.get_statements()[0] {
+            Statement::Let { name, value, .. } => {
+                assert_eq!(name.get_span(), Span::new(4, 5));
+                assert_eq!(value.get_span(), Span::new(8, 20));
+
+                match value {
+                    Expression::Infix { right, .. } => {
+                        assert_eq!(right.get_span(), Span::new(13, 20))
+                    }
+                    e => panic!("Expected infix expression, got {:?}", e),
+                }
+            }
+            s => panic!("Expected let statement, got {:?}", s),
+        }
+
+        match &program.get_statements()[1] {
+            Statement::Expression {
+                value: Expression::Call { arguments, .. },
+                ..
+            } => {
+                let spans: Vec<Span> = arguments.iter().map(|a| a.get_span()).collect();
+                assert_eq!(spans, vec![Span::new(26, 27), Span::new(29, 40)]);
+            }
+            s => panic!("Expected call expression, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn test_error_diagnostics() {
+        let input = "let x = (1 + 2;";
+        let errors = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .unwrap_err();
+
+        assert_eq!(
+            errors.get_errors()[0]
+                .to_diagnostic()
+                .render("test", input, false),
+            "error: Expected next token to be of type \"Rparen\", but got \"Semicolon\"
+ --> test:1:15
+  |
+1 | let x = (1 + 2;
+  |               ^
+"
+        );
+
+        let input = "let s = \"abc";
+        let errors = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .unwrap_err();
+
+        assert!(errors.get_errors()[0]
+            .to_diagnostic()
+            .render("test", input, false)
+            .ends_with("  = help: add a closing `\"` to end the string\n"));
+    }
+
+    fn parse_errors(input: &str) -> Vec<String> {
+        let lexer = Lexer::new(input.into());
+        let errors = Parser::new(lexer).parse_program().unwrap_err();
+
+        errors
+            .get_errors()
+            .iter()
+            .map(|e| e.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_at_statement_boundaries() {
+        let errors = parse_errors("let x 5; let y = 3; let = 1; return 2; x +;");
+
+        assert_eq!(
+            errors,
+            vec![
+                "Expected next token to be of type \"Assign\", but got \"Int\"",
+                "Expected next token to be of type \"Ident\", but got \"Assign\"",
+                "No prefix parse function for token type: Semicolon",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recover_inside_blocks() {
+        let errors = parse_errors(
+            "
+            let f = fn(x) {
+                let = 1;
+                x
+            };
+            let g = fn(x { x };
+            let y = ;
+            ",
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                "Expected next token to be of type \"Ident\", but got \"Assign\"",
+                "Expected next token to be of type \"Rparen\", but got \"Lbrace\"",
+                "No prefix parse function for token type: Semicolon",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_errors() {
+        let lexer = Lexer::new("let x = 1; let = 2; x + 1; let y = ;".into());
+        let (program, errors) = Parser::new(lexer).parse_program_with_errors();
+
+        let statements: Vec<String> = program
+            .get_statements()
+            .iter()
+            .map(|s| format!("{:#}", s))
+            .collect();
+        assert_eq!(statements, vec!["let x = 1;", "(x + 1);"]);
+        assert_eq!(errors.get_errors().len(), 2);
+    }
+
+    #[test]
+    fn test_recovery_terminates_at_eof() {
+        let tests = [
+            ("let", 1),
+            ("let x", 1),
+            ("let x =", 1),
+            ("return", 1),
+            ("fn(x) { x", 1),
+            ("if (x) { let y = }", 1),
+            ("}}}", 3),
+            ("\"abc", 1),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse_errors(input).len(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_errors_are_capped() {
+        let input = "let = 1;".repeat(50);
+
+        assert_eq!(parse_errors(&input).len(), super::MAX_ERRORS);
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let program = parse("  ");
+        assert!(program.get_statements().is_empty());
+    }
+
+    #[test]
+    fn test_missing_prefix_parse_function() {
+        let lexer = Lexer::new("let x = *;".into());
+        let parser = Parser::new(lexer);
+
+        assert!(parser.parse_program().is_err());
+    }
 }
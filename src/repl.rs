@@ -1,26 +1,288 @@
-use crate::{lexer::Lexer, token::TokenType};
-use std::io::Write;
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::path::PathBuf;
+use toy_interpreter::{
+    compiler,
+    diagnostics::{self, Diagnostic},
+    Interpreter, Lexer, Parser, Program, TokenType,
+};
 
-pub fn start() {
-    let mut buffer = String::new();
+const HELP: &str = ":tokens    print the tokens of each input, including comments
+:ast       print the syntax tree of each input
+:bytecode  print the compiled instructions and constants of each input
+:eval      evaluate each input (default)
+:help      print this help
+:quit      leave the repl (or ctrl-d)";
 
-    loop {
-        buffer.clear();
+/// Name of the history file in the home directory.
+const HISTORY_FILE: &str = ".toy_interpreter_history";
+
+/// Stage of the pipeline whose output is printed for each input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Tokens,
+    Ast,
+    Bytecode,
+    Eval,
+}
+
+/// State kept between the inputs of one repl session.
+struct Session {
+    mode: Mode,
+    interpreter: Interpreter,
+}
+
+impl Session {
+    fn new() -> Session {
+        Session {
+            mode: Mode::Eval,
+            interpreter: Interpreter::new(),
+        }
+    }
 
-        // small prompt
-        print!(">> ");
-        std::io::stdout().flush().unwrap();
+    /// Handle one input, which is either a meta command starting with `:` or code that is run
+    /// through the current mode.
+    fn handle(&mut self, input: &str) -> Result<String, Vec<Diagnostic>> {
+        if let Some(command) = input.trim().strip_prefix(':') {
+            return Ok(self.run_command(command));
+        }
+
+        match self.mode {
+            Mode::Tokens => Ok(Session::tokens(input)),
+            Mode::Ast => Session::parse(input).map(|program| format!("{:#?}", program)),
+            Mode::Bytecode => Session::parse(input).and_then(|program| {
+                compiler::compile(&program, input)
+                    .map(|bytecode| {
+                        compiler::disassemble(&bytecode, Some(input))
+                            .trim_end()
+                            .into()
+                    })
+                    .map_err(|error| vec![error.to_diagnostic()])
+            }),
+            Mode::Eval => self
+                .interpreter
+                .eval_str(input)
+                .map(|result| result.to_string())
+                .map_err(|error| error.to_diagnostics()),
+        }
+    }
+
+    fn run_command(&mut self, command: &str) -> String {
+        let mode = match command {
+            "tokens" => Mode::Tokens,
+            "ast" => Mode::Ast,
+            "bytecode" => Mode::Bytecode,
+            "eval" => Mode::Eval,
+            "help" => return HELP.into(),
+            _ => return format!("unknown command :{}, see :help", command),
+        };
 
-        std::io::stdin().read_line(&mut buffer).unwrap();
+        self.mode = mode;
+        format!("switched to {} mode", command)
+    }
+
+    fn tokens(input: &str) -> String {
+        let mut lexer = Lexer::new(input.into()).with_comments();
+        let mut tokens = Vec::new();
 
-        let mut lexer = Lexer::new(buffer.clone());
         loop {
             let token = lexer.next_token();
-            println!("{:?}", token);
+            tokens.push(format!("{:?}", token));
 
             if token.get_type() == TokenType::Eof {
                 break;
             }
         }
+
+        tokens.join("\n")
+    }
+
+    fn parse(input: &str) -> Result<Program, Vec<Diagnostic>> {
+        Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .map_err(|errors| {
+                errors
+                    .get_errors()
+                    .iter()
+                    .map(|e| e.to_diagnostic())
+                    .collect()
+            })
+    }
+}
+
+/// Check if the input is missing closing delimiters or quotes and should be continued on the
+/// next line.
+fn is_incomplete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input.into());
+    let mut depth: i32 = 0;
+
+    loop {
+        let token = lexer.next_token();
+        match token.get_type() {
+            TokenType::Lparen | TokenType::Lbrace | TokenType::Lbracket => depth += 1,
+            TokenType::Rparen | TokenType::Rbrace | TokenType::Rbracket => depth -= 1,
+            TokenType::Illegal
+                if token.get_literal() == "unterminated string literal"
+                    || token.get_literal() == "unterminated block comment" =>
+            {
+                return true
+            }
+            TokenType::Eof => return depth > 0,
+            _ => {}
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+pub fn start() {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error: could not start the line editor: {}", e);
+            return;
+        }
+    };
+
+    let history = history_path();
+    if let Some(history) = &history {
+        // there is no history on the first start
+        let _ = editor.load_history(history);
+    }
+
+    let mut session = Session::new();
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() { ">> " } else { ".. " };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+            // ctrl-c drops the current input
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break;
+            }
+        }
+
+        if is_incomplete(&buffer) {
+            continue;
+        }
+
+        let input = std::mem::take(&mut buffer);
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(input.trim_end());
+
+        if matches!(input.trim(), ":quit" | ":q") {
+            break;
+        }
+
+        match session.handle(&input) {
+            Ok(output) => println!("{}", output),
+            Err(errors) => diagnostics::emit(&errors, "repl", &input),
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(e) = editor.save_history(history) {
+            eprintln!("error: could not save the history: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_incomplete, Mode, Session};
+
+    #[test]
+    fn test_switch_modes() {
+        let mut session = Session::new();
+        assert_eq!(session.mode, Mode::Eval);
+
+        for (command, mode) in [
+            (":tokens", Mode::Tokens),
+            (":ast\n", Mode::Ast),
+            (":bytecode", Mode::Bytecode),
+            (" :eval ", Mode::Eval),
+        ] {
+            assert!(session.handle(command).is_ok());
+            assert_eq!(session.mode, mode, "command: {}", command);
+        }
+
+        assert_eq!(
+            session.handle(":nope"),
+            Ok("unknown command :nope, see :help".into())
+        );
+        assert_eq!(session.mode, Mode::Eval);
+    }
+
+    #[test]
+    fn test_modes_output() {
+        let mut session = Session::new();
+
+        session.handle(":tokens").unwrap();
+        let tokens = session.handle("let x // y").unwrap();
+        assert_eq!(tokens.lines().count(), 4);
+        assert!(tokens.lines().next().unwrap().contains("Let"));
+        assert!(tokens.lines().nth(2).unwrap().contains("Comment"));
+
+        session.handle(":ast").unwrap();
+        assert!(session.handle("let x = 1;").unwrap().contains("Let {"));
+        assert!(session.handle("let x = ;").is_err());
+
+        session.handle(":bytecode").unwrap();
+        assert_eq!(
+            session.handle("1 + 2"),
+            Ok("== main ==\n   1 | 1 + 2\n0000 OpConstant 0 (1)\n0003 OpConstant 1 (2)\n0006 OpAdd\n0007 OpPop".into())
+        );
+
+        session.handle(":eval").unwrap();
+        assert_eq!(session.handle("1 + 2"), Ok("3".into()));
+    }
+
+    #[test]
+    fn test_environment_persists() {
+        let mut session = Session::new();
+
+        assert_eq!(session.handle("let x = 5;"), Ok("null".into()));
+        session.handle(":tokens").unwrap();
+        session.handle(":eval").unwrap();
+        assert_eq!(session.handle("x * 2"), Ok("10".into()));
+        assert!(session.handle("y").is_err());
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = [
+            ("let x = 5;", false),
+            ("let f = fn(x) {", true),
+            ("let f = fn(x) {\n x +", true),
+            ("let f = fn(x) {\n x\n};", false),
+            ("add(1,", true),
+            ("let a = [1,\n2", true),
+            ("let s = \"multi", true),
+            ("let x = 1; /* not\n/* done */", true),
+            ("let x = 1; // {", false),
+            ("let s = \"multi\nline\";", false),
+            ("let s = \"{(\";", false),
+            ("}", false),
+            ("", false),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(is_incomplete(input), expected, "input: {}", input);
+        }
     }
 }
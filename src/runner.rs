@@ -0,0 +1,295 @@
+use std::{io::Read, path::Path, process::ExitCode};
+
+use toy_interpreter::{
+    compiler::{self, Bytecode},
+    diagnostics::{self, Diagnostic},
+    formatter,
+    vm::Vm,
+    Interpreter, Lexer, Parser, Value,
+};
+
+/// Backend that runs the scripts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    Evaluator,
+    Vm,
+}
+
+/// Run a script file, or the input from stdin if `path` is `-`. Prints the result of the script
+/// unless it is `null`. Compiled bytecode files are recognized by their header and always run on
+/// the vm.
+pub fn run(path: &str, engine: Engine) -> ExitCode {
+    let Some((file_name, bytes)) = read_input(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    if bytes.starts_with(&compiler::MAGIC) {
+        return run_bytecode(&file_name, &bytes);
+    }
+
+    let Some(source) = into_source(&file_name, bytes) else {
+        return ExitCode::FAILURE;
+    };
+
+    match execute(&source, engine) {
+        Ok(result) => print_result(result),
+        Err(diagnostics) => {
+            diagnostics::emit(&diagnostics, &file_name, &source);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Compile a script to a bytecode file, which is written next to the script unless `output` is
+/// given.
+pub fn compile(path: &str, output: Option<&str>) -> ExitCode {
+    let output = match output {
+        Some(output) => Path::new(output).to_path_buf(),
+        None if path == "-" => {
+            eprintln!("error: the output file is required when compiling stdin, use -o <file>");
+            return ExitCode::FAILURE;
+        }
+        None => Path::new(path).with_extension("tbc"),
+    };
+
+    let Some((file_name, bytes)) = read_input(path) else {
+        return ExitCode::FAILURE;
+    };
+    let Some(source) = into_source(&file_name, bytes) else {
+        return ExitCode::FAILURE;
+    };
+
+    let bytecode = match compile_source(&source) {
+        Ok(bytecode) => bytecode,
+        Err(diagnostics) => {
+            diagnostics::emit(&diagnostics, &file_name, &source);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = std::fs::write(&output, bytecode.to_bytes()) {
+        eprintln!("error: could not write {}: {}", output.display(), e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Print the instructions of a script or bytecode file, the source lines are only shown for
+/// scripts.
+pub fn disassemble(path: &str) -> ExitCode {
+    let Some((file_name, bytes)) = read_input(path) else {
+        return ExitCode::FAILURE;
+    };
+
+    if bytes.starts_with(&compiler::MAGIC) {
+        return match Bytecode::from_bytes(&bytes) {
+            Ok(bytecode) => {
+                print!("{}", compiler::disassemble(&bytecode, None));
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("error: could not load {}: {}", file_name, e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let Some(source) = into_source(&file_name, bytes) else {
+        return ExitCode::FAILURE;
+    };
+
+    match compile_source(&source) {
+        Ok(bytecode) => {
+            print!("{}", compiler::disassemble(&bytecode, Some(&source)));
+            ExitCode::SUCCESS
+        }
+        Err(diagnostics) => {
+            diagnostics::emit(&diagnostics, &file_name, &source);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Format scripts in place, `-` prints the formatted input from stdin. With `check` nothing is
+/// written, the scripts that are not formatted are reported instead.
+pub fn format(paths: &[&str], check: bool) -> ExitCode {
+    let mut code = ExitCode::SUCCESS;
+
+    for path in paths {
+        let Some((file_name, bytes)) = read_input(path) else {
+            code = ExitCode::FAILURE;
+            continue;
+        };
+        let Some(source) = into_source(&file_name, bytes) else {
+            code = ExitCode::FAILURE;
+            continue;
+        };
+
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                let diagnostics: Vec<Diagnostic> = errors
+                    .get_errors()
+                    .iter()
+                    .map(|e| e.to_diagnostic())
+                    .collect();
+                diagnostics::emit(&diagnostics, &file_name, &source);
+                code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                eprintln!("error: {} is not formatted", file_name);
+                code = ExitCode::FAILURE;
+            }
+        } else if *path == "-" {
+            print!("{}", formatted);
+        } else if formatted != source {
+            if let Err(e) = std::fs::write(path, formatted) {
+                eprintln!("error: could not write {}: {}", path, e);
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    code
+}
+
+/// Read the file, or stdin if `path` is `-`, together with the name used in error messages.
+fn read_input(path: &str) -> Option<(String, Vec<u8>)> {
+    let result = if path == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map(|_| ("<stdin>".into(), bytes))
+    } else {
+        std::fs::read(path).map(|bytes| (path.into(), bytes))
+    };
+
+    match result {
+        Ok(input) => Some(input),
+        Err(e) => {
+            eprintln!("error: could not read {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn into_source(file_name: &str, bytes: Vec<u8>) -> Option<String> {
+    match String::from_utf8(bytes) {
+        Ok(source) => Some(source),
+        Err(_) => {
+            eprintln!(
+                "error: could not read {}: the file is not valid utf-8",
+                file_name
+            );
+            None
+        }
+    }
+}
+
+/// Runtime errors of bytecode files only point to the line, as the source is not available.
+fn run_bytecode(file_name: &str, bytes: &[u8]) -> ExitCode {
+    let bytecode = match Bytecode::from_bytes(bytes) {
+        Ok(bytecode) => bytecode,
+        Err(e) => {
+            eprintln!("error: could not load {}: {}", file_name, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match Vm::new(&bytecode).run() {
+        Ok(result) => print_result(result),
+        Err(error) => {
+            let line = bytecode.get_line(error.get_span().get_start());
+            eprintln!(
+                "error: {}\n --> {}:{}",
+                error.get_message(),
+                file_name,
+                line
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_result(result: Value) -> ExitCode {
+    if result != Value::Null {
+        println!("{}", result);
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn compile_source(source: &str) -> Result<Bytecode, Vec<Diagnostic>> {
+    let program = Parser::new(Lexer::new(source.into()))
+        .parse_program()
+        .map_err(|errors| {
+            errors
+                .get_errors()
+                .iter()
+                .map(|e| e.to_diagnostic())
+                .collect::<Vec<_>>()
+        })?;
+
+    compiler::compile(&program, source).map_err(|error| vec![error.to_diagnostic()])
+}
+
+fn execute(source: &str, engine: Engine) -> Result<Value, Vec<Diagnostic>> {
+    match engine {
+        Engine::Evaluator => Interpreter::new()
+            .eval_str(source)
+            .map_err(|error| error.to_diagnostics()),
+        Engine::Vm => Vm::new(&compile_source(source)?)
+            .run()
+            .map_err(|error| vec![error.to_diagnostic()]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile_source, execute, Engine};
+    use toy_interpreter::{compiler::Bytecode, vm::Vm, Value};
+
+    #[test]
+    fn test_execute() {
+        let source = "
+            let fibonacci = fn(n) { if (n < 2) { n } else { fibonacci(n - 1) + fibonacci(n - 2) } };
+            fibonacci(10);
+        ";
+
+        for engine in [Engine::Evaluator, Engine::Vm] {
+            assert_eq!(
+                execute(source, engine),
+                Ok(Value::Integer(55)),
+                "{:?}",
+                engine
+            );
+        }
+    }
+
+    #[test]
+    fn test_execute_errors() {
+        for engine in [Engine::Evaluator, Engine::Vm] {
+            let parse_errors = execute("let x = ; let = 5;", engine).unwrap_err();
+            assert_eq!(parse_errors.len(), 2);
+
+            let runtime_errors = execute("let x = 5; x + true", engine).unwrap_err();
+            assert_eq!(
+                runtime_errors,
+                execute("let x = 5; x + true", Engine::Evaluator).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytecode_round_trip() {
+        let bytecode = compile_source("let double = fn(x) { x * 2 };\ndouble(21)").unwrap();
+        let loaded = Bytecode::from_bytes(&bytecode.to_bytes()).unwrap();
+
+        assert_eq!(Vm::new(&loaded).run(), Ok(Value::Integer(42)));
+    }
+}
@@ -0,0 +1,141 @@
+/// Range in the source input, `end` is exclusive. Offsets are byte offsets into the input.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Span {
+    start: usize,
+    end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn get_start(&self) -> usize {
+        self.start
+    }
+
+    pub fn get_end(&self) -> usize {
+        self.end
+    }
+
+    /// Smallest span covering both spans.
+    pub fn merge(&self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// 1-based line and column of an offset in the source, columns are counted in characters.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Location {
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+/// Converts offsets of spans into line and column numbers of the source they were created from.
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> SourceMap {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+
+        SourceMap {
+            source: source.to_string(),
+            line_starts,
+        }
+    }
+
+    /// Offsets of the first character of each line.
+    pub fn get_line_starts(&self) -> &Vec<usize> {
+        &self.line_starts
+    }
+
+    pub fn get_location(&self, offset: usize) -> Location {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+
+        let line_start = self.line_starts[line];
+        let column = match self.source.get(line_start..offset) {
+            Some(text) => text.chars().count(),
+            // offsets inside of a character or past the end of the source
+            None => offset - line_start,
+        };
+
+        Location {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceMap, Span};
+
+    #[test]
+    fn test_span_merge() {
+        let span = Span::new(4, 6).merge(Span::new(1, 2));
+
+        assert_eq!(span, Span::new(1, 6));
+    }
+
+    #[test]
+    fn test_source_map_location() {
+        let source_map = SourceMap::new("let x = 1;\nlet y = 2;\n\nx + y");
+        let tests = [
+            (0, (1, 1)),
+            (4, (1, 5)),
+            (10, (1, 11)),
+            (11, (2, 1)),
+            (15, (2, 5)),
+            (22, (3, 1)),
+            (23, (4, 1)),
+            (27, (4, 5)),
+        ];
+
+        for (offset, (line, column)) in tests {
+            let location = source_map.get_location(offset);
+            assert_eq!(
+                (location.get_line(), location.get_column()),
+                (line, column),
+                "offset: {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn test_source_map_counts_characters() {
+        let source_map = SourceMap::new("\"äöü\" + y\nx");
+        let tests = [(7, (1, 5)), (10, (1, 8)), (13, (2, 1))];
+
+        for (offset, (line, column)) in tests {
+            let location = source_map.get_location(offset);
+            assert_eq!(
+                (location.get_line(), location.get_column()),
+                (line, column),
+                "offset: {}",
+                offset
+            );
+        }
+    }
+}
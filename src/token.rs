@@ -1,13 +1,18 @@
-#[derive(Debug, Default, Eq, PartialEq, Clone)]
+use crate::span::Span;
+
+#[derive(Debug, Default, Eq, PartialEq, Hash, Clone)]
 pub enum TokenType {
     #[default]
     Illegal,
     Eof,
+    // only produced when the lexer is asked to keep comments
+    Comment,
 
     // Identifiers + literals
     Ident,
     Int,
     Float,
+    String,
 
     // Operators
     Assign,
@@ -16,20 +21,28 @@ pub enum TokenType {
     Bang,
     Asterisk,
     Slash,
+    Percent,
 
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Eq,
     NotEq,
+    And,
+    Or,
 
     // Delimiters
     Comma,
+    Colon,
     Semicolon,
 
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
 
     // Keywords
     Function,
@@ -41,22 +54,36 @@ pub enum TokenType {
     Return,
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Clone)]
+/// Value of a number literal, parsed by the lexer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
 pub struct Token {
     r#type: TokenType,
     literal: String,
-    start_postion: usize,
+    span: Span,
+    number: Option<Number>,
 }
 
 impl Token {
-    pub fn new(r#type: TokenType, literal: String, start_postion: usize) -> Token {
+    pub fn new(r#type: TokenType, literal: String, span: Span) -> Token {
         Token {
             r#type,
             literal,
-            start_postion,
+            span,
+            number: None,
         }
     }
 
+    pub(crate) fn with_number(mut self, number: Number) -> Token {
+        self.number = Some(number);
+        self
+    }
+
     pub fn get_type(&self) -> TokenType {
         self.r#type.clone()
     }
@@ -65,10 +92,14 @@ impl Token {
         self.literal.clone()
     }
 
-    pub fn get_position(&self) -> (usize, usize) {
-        (
-            self.start_postion,
-            self.start_postion + self.get_literal().len(),
-        )
+    /// Range of the token in the input, which can differ from the length of the literal, e.g. for
+    /// strings or normalized numbers.
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    /// Parsed value of `Int` and `Float` tokens.
+    pub(crate) fn get_number(&self) -> Option<Number> {
+        self.number
     }
 }
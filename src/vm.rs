@@ -0,0 +1,425 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    compiler::{read_operands, Bytecode, Constant, Opcode, Origin},
+    evaluator::{
+        self, builtins, Binding, Closure, HashPair, Object, RuntimeError, Variable, MAX_CALL_DEPTH,
+    },
+    span::Span,
+};
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    locals: Vec<Variable>,
+    /// Height of the stack when the function was called, without the function and arguments.
+    base_pointer: usize,
+}
+
+/// Stack machine running the output of the compiler, it produces the same results and errors as
+/// the evaluator.
+pub struct Vm {
+    constants: Vec<Constant>,
+    global_names: Vec<String>,
+    globals: Vec<Option<Object>>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    last_popped: Object,
+}
+
+impl Vm {
+    pub fn new(bytecode: &Bytecode) -> Vm {
+        let main = Closure::new(Rc::clone(bytecode.get_main()), Vec::new());
+
+        Vm {
+            constants: bytecode.get_constants().clone(),
+            global_names: bytecode.get_global_names().clone(),
+            globals: vec![None; bytecode.get_global_names().len()],
+            stack: Vec::new(),
+            frames: vec![Frame {
+                closure: Rc::new(main),
+                ip: 0,
+                locals: Vec::new(),
+                base_pointer: 0,
+            }],
+            last_popped: Object::Null,
+        }
+    }
+
+    /// Run the program, the result is the value of the last expression statement.
+    pub fn run(&mut self) -> Result<Object, RuntimeError> {
+        loop {
+            let frame = self
+                .frames
+                .last_mut()
+                .expect("the main frame is never left");
+            let instructions = frame.closure.get_function().get_instructions();
+
+            // only the main program runs off its end, functions always return
+            let Some(&byte) = instructions.get(frame.ip) else {
+                return Ok(std::mem::replace(&mut self.last_popped, Object::Null));
+            };
+            let offset = frame.ip;
+            let op = Opcode::from_byte(byte)
+                .expect("compiled and loaded bytecode only has known opcodes");
+            let (operands, read) = read_operands(op, &instructions[offset + 1..]);
+            frame.ip += 1 + read;
+
+            match op {
+                Opcode::Constant => {
+                    let value = match &self.constants[operands[0]] {
+                        Constant::Integer(value) => Object::Integer(*value),
+                        Constant::Float(value) => Object::Float(*value),
+                        Constant::String(value) => Object::String(value.clone()),
+                        Constant::Function(_) => unreachable!("functions are loaded as closures"),
+                    };
+                    self.push(value);
+                }
+                Opcode::Pop => self.last_popped = self.pop(),
+                Opcode::True => self.push(Object::Boolean(true)),
+                Opcode::False => self.push(Object::Boolean(false)),
+                Opcode::Null => self.push(Object::Null),
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Mod
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::LessThan
+                | Opcode::GreaterThan
+                | Opcode::LessEqual
+                | Opcode::GreaterEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let operator = infix_operator(op);
+                    let result =
+                        evaluator::eval_infix_expression(operator, left, right, Span::default());
+                    self.push_result(result, offset)?;
+                }
+                Opcode::Minus | Opcode::Bang => {
+                    let right = self.pop();
+                    let operator = if op == Opcode::Minus { "-" } else { "!" };
+                    let result =
+                        evaluator::eval_prefix_expression(operator, right, Span::default());
+                    self.push_result(result, offset)?;
+                }
+                Opcode::Jump => self.current_frame().ip = operands[0],
+                Opcode::JumpNotTruthy => {
+                    let condition = self.pop();
+                    if !evaluator::is_truthy(&condition) {
+                        self.current_frame().ip = operands[0];
+                    }
+                }
+                Opcode::GetGlobal => {
+                    let value = self.get_global(operands[0], offset)?;
+                    self.push(value);
+                }
+                Opcode::SetGlobal => self.globals[operands[0]] = Some(self.pop()),
+                Opcode::GetLocal => {
+                    let variable = Rc::clone(&self.current_frame().locals[operands[0]]);
+                    let value = self.read(&variable, offset)?;
+                    self.push(value);
+                }
+                Opcode::SetLocal => {
+                    let value = Binding::Value(self.pop());
+                    *self.current_frame().locals[operands[0]].borrow_mut() = value;
+                }
+                Opcode::GetFree => {
+                    let variable = Rc::clone(&self.current_frame().closure.get_free()[operands[0]]);
+                    let value = self.read(&variable, offset)?;
+                    self.push(value);
+                }
+                Opcode::Closure => {
+                    let Constant::Function(function) = &self.constants[operands[0]] else {
+                        unreachable!("closures are only created from functions");
+                    };
+                    let function = Rc::clone(function);
+
+                    let frame = self.current_frame();
+                    let free = function
+                        .get_free()
+                        .iter()
+                        .map(|origin| match *origin {
+                            Origin::Local(index) => Rc::clone(&frame.locals[index]),
+                            Origin::Free(index) => Rc::clone(&frame.closure.get_free()[index]),
+                            _ => unreachable!("only variables of functions are captured"),
+                        })
+                        .collect();
+                    self.push(Object::Closure(Rc::new(Closure::new(function, free))));
+                }
+                Opcode::Array => {
+                    let elements = self.stack.split_off(self.stack.len() - operands[0]);
+                    self.push(Object::Array(elements));
+                }
+                Opcode::Hash => {
+                    let values = self.stack.split_off(self.stack.len() - operands[0] * 2);
+                    let mut pairs = HashMap::with_capacity(operands[0]);
+
+                    let mut values = values.into_iter();
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        // each key is checked by `OpHashKey` already, unless the bytecode file was
+                        // written by hand
+                        let Some(hash_key) = key.get_hash_key() else {
+                            let message = format!("unusable as hash key: {}", key.get_type_name());
+                            return Err(self.error(message, offset));
+                        };
+                        pairs.insert(hash_key, HashPair::new(key, value));
+                    }
+
+                    self.push(Object::Hash(pairs));
+                }
+                Opcode::HashKey => {
+                    let key = self
+                        .stack
+                        .last()
+                        .expect("compiled and loaded bytecode keeps the stack balanced");
+                    let result = evaluator::get_hash_key(key, Span::default());
+                    if let Err(error) = result {
+                        return Err(self.error(error.get_message().clone(), offset));
+                    }
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    let result = evaluator::eval_index_expression(left, index, Span::default());
+                    self.push_result(result, offset)?;
+                }
+                Opcode::Call => self.call(operands[0], offset)?,
+                Opcode::ReturnValue | Opcode::Return => {
+                    let value = match op {
+                        Opcode::ReturnValue => self.pop(),
+                        _ => Object::Null,
+                    };
+
+                    // a return in the main program ends it
+                    if self.frames.len() == 1 {
+                        return Ok(value);
+                    }
+
+                    let frame = self.frames.pop().expect("checked above");
+                    self.stack.truncate(frame.base_pointer);
+                    self.push(value);
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, num_arguments: usize, offset: usize) -> Result<(), RuntimeError> {
+        let callee = self.stack.len() - num_arguments - 1;
+
+        match &self.stack[callee] {
+            Object::Closure(closure) => {
+                let closure = Rc::clone(closure);
+                let function = closure.get_function();
+
+                if function.get_parameters().len() != num_arguments {
+                    let message = format!(
+                        "wrong number of arguments: want={}, got={}",
+                        function.get_parameters().len(),
+                        num_arguments
+                    );
+                    return Err(self.error(message, offset));
+                }
+                // the main program has a frame as well
+                if self.frames.len() > MAX_CALL_DEPTH {
+                    return Err(self.error("stack overflow".into(), offset));
+                }
+
+                // parameters are bound right away, the other locals once their `let` runs
+                let mut locals = Vec::with_capacity(function.get_num_locals());
+                locals.extend(
+                    self.stack
+                        .drain(callee + 1..)
+                        .map(|argument| Rc::new(RefCell::new(Binding::Value(argument)))),
+                );
+                self.stack.pop();
+                locals.extend(function.get_fallbacks().iter().map(|origin| {
+                    let binding = match *origin {
+                        Origin::Free(index) => {
+                            Binding::Outer(Rc::clone(&closure.get_free()[index]))
+                        }
+                        Origin::Global(index) => Binding::Global(index),
+                        Origin::Local(_) => unreachable!("locals fall back to enclosing scopes"),
+                    };
+                    Rc::new(RefCell::new(binding))
+                }));
+
+                self.frames.push(Frame {
+                    closure,
+                    ip: 0,
+                    locals,
+                    base_pointer: callee,
+                });
+            }
+            Object::Builtin(builtin) => {
+                let builtin = builtin.clone();
+                let arguments = self.stack.split_off(callee + 1);
+                self.stack.pop();
+
+                match builtin.call(&arguments) {
+                    Ok(result) => self.push(result),
+                    Err(error) => return Err(self.error(error.get_message().clone(), offset)),
+                }
+            }
+            other => {
+                let message = format!("not a function: {}", other.get_type_name());
+                return Err(self.error(message, offset));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Value of a local or free variable, following unbound variables to the enclosing scopes.
+    fn read(&self, variable: &Variable, offset: usize) -> Result<Object, RuntimeError> {
+        let mut variable = Rc::clone(variable);
+        loop {
+            let next = match &*variable.borrow() {
+                Binding::Value(value) => return Ok(value.clone()),
+                Binding::Outer(outer) => Rc::clone(outer),
+                Binding::Global(index) => return self.get_global(*index, offset),
+            };
+            variable = next;
+        }
+    }
+
+    /// A global that is not bound (yet) can still be a builtin of the same name.
+    fn get_global(&self, index: usize, offset: usize) -> Result<Object, RuntimeError> {
+        if let Some(value) = &self.globals[index] {
+            return Ok(value.clone());
+        }
+
+        let name = &self.global_names[index];
+        builtins::get(name)
+            .ok_or_else(|| self.error(format!("identifier not found: {}", name), offset))
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("the main frame is never left")
+    }
+
+    fn push(&mut self, value: Object) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack
+            .pop()
+            .expect("compiled and loaded bytecode keeps the stack balanced")
+    }
+
+    /// Push the result of an operation shared with the evaluator, or report its error at the
+    /// instruction.
+    fn push_result(
+        &mut self,
+        result: Result<Object, RuntimeError>,
+        offset: usize,
+    ) -> Result<(), RuntimeError> {
+        match result {
+            Ok(result) => {
+                self.push(result);
+                Ok(())
+            }
+            Err(error) => Err(self.error(error.get_message().clone(), offset)),
+        }
+    }
+
+    /// Error at the source of the instruction at `offset` in the current function.
+    fn error(&self, message: String, offset: usize) -> RuntimeError {
+        let frame = self.frames.last().expect("the main frame is never left");
+        RuntimeError::new(message, frame.closure.get_function().get_span(offset))
+    }
+}
+
+fn infix_operator(op: Opcode) -> &'static str {
+    match op {
+        Opcode::Add => "+",
+        Opcode::Sub => "-",
+        Opcode::Mul => "*",
+        Opcode::Div => "/",
+        Opcode::Mod => "%",
+        Opcode::Equal => "==",
+        Opcode::NotEqual => "!=",
+        Opcode::LessThan => "<",
+        Opcode::GreaterThan => ">",
+        Opcode::LessEqual => "<=",
+        Opcode::GreaterEqual => ">=",
+        _ => unreachable!("{} is not an infix operator", op.get_name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vm;
+    use crate::{compiler, evaluator::Object, lexer::Lexer, parser::Parser, span::Span};
+
+    fn run_input(input: &str) -> Result<Object, crate::evaluator::RuntimeError> {
+        let program = Parser::new(Lexer::new(input.into()))
+            .parse_program()
+            .expect("Program could not be parsed correctly");
+        let bytecode = compiler::compile(&program, input).expect("Program could not be compiled");
+
+        Vm::new(&bytecode).run()
+    }
+
+    #[test]
+    fn test_stack_is_balanced() {
+        let input =
+            "let f = fn(a, b) { let c = a + b; if (c > 2) { c } }; f(1, 2); f(1, 1); [f(2, 2)]";
+        assert_eq!(
+            run_input(input),
+            Ok(Object::Array(vec![Object::Integer(4)]))
+        );
+    }
+
+    #[test]
+    fn test_closures_capture_values() {
+        let input = "
+            let adder = fn(a) { fn(b) { fn(c) { a + b + c } } };
+            let add = adder(1)(2);
+            add(3) + add(4)
+        ";
+        assert_eq!(run_input(input), Ok(Object::Integer(13)));
+    }
+
+    #[test]
+    fn test_closures_share_variables() {
+        let input = "
+            let f = fn(x) {
+                let get = fn() { x + y };
+                let y = 1;
+                let first = get();
+                let x = 10;
+                [first, get()]
+            };
+            f(1)
+        ";
+        assert_eq!(
+            run_input(input),
+            Ok(Object::Array(vec![Object::Integer(2), Object::Integer(11)]))
+        );
+    }
+
+    #[test]
+    fn test_recursive_local_function() {
+        let input = "
+            let wrapper = fn() {
+                let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };
+                count(100)
+            };
+            wrapper()
+        ";
+        assert_eq!(run_input(input), Ok(Object::Integer(100)));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let error = run_input("let f = fn(x) { f(x) + 1 }; f(1)").unwrap_err();
+
+        assert_eq!(error.get_message(), "stack overflow");
+        assert_eq!(error.get_span(), Span::new(16, 20));
+    }
+}